@@ -0,0 +1,749 @@
+use crate::{
+    cache::{cache_key, CacheBackend},
+    config::{mask, Config, RateLimit},
+    error::Upstream,
+    keys::KeyTracker,
+    logging::{record_request_cache, upstream},
+    metrics::record_cache,
+    structs::KeyHealth,
+};
+use arc_swap::ArcSwap;
+use lazy_static::lazy_static;
+use rs_pixel::{
+    response::{
+        boosters_response::BoostersResponse,
+        counts_response::CountsResponse,
+        guild_response::GuildResponse,
+        leaderboards_response::LeaderboardsResponse,
+        player_response::PlayerResponse,
+        punishment_stats_response::PunishmentStatsResponse,
+        recent_games_response::RecentGamesResponse,
+        skyblock::{
+            skyblock_auction_response::SkyblockAuctionResponse,
+            skyblock_auctions_ended_response::SkyblockAuctionsEndedResponse,
+            skyblock_auctions_response::SkyblockAuctionsResponse,
+            skyblock_bazaar_response::SkyblockBazaarResponse,
+            skyblock_bingo_response::SkyblockBingoResponse,
+            skyblock_fire_sales_response::SkyblockFireSalesResponse,
+            skyblock_news_response::SkyblockNewsResponse,
+            skyblock_profile_response::SkyblockProfileResponse,
+            skyblock_profiles_response::SkyblockProfilesResponse,
+        },
+        status_response::StatusResponse,
+    },
+    util::error::Error,
+    Config as RsPixelConfig, ConfigBuilder, HypixelEndpoint, RsPixel,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::warn;
+
+lazy_static! {
+    /// Paths of the endpoints that are requested without an API key, which don't use any quota
+    static ref KEYLESS_PATHS: HashSet<String> = [
+        HypixelEndpoint::SKYBLOCK_AUCTIONS,
+        HypixelEndpoint::SKYBLOCK_AUCTIONS_ENDED,
+        HypixelEndpoint::SKYBLOCK_BAZAAR,
+        HypixelEndpoint::SKYBLOCK_FIRESALES,
+        HypixelEndpoint::RESOURCES_GAMES,
+        HypixelEndpoint::RESOURCES_ACHIEVEMENTS,
+        HypixelEndpoint::RESOURCES_CHALLENGES,
+        HypixelEndpoint::RESOURCES_QUESTS,
+        HypixelEndpoint::RESOURCES_GUILD_ACHIEVEMENTS,
+        HypixelEndpoint::RESOURCES_VANITY_PETS,
+        HypixelEndpoint::RESOURCES_VANITY_COMPANIONS,
+        HypixelEndpoint::RESOURCES_SKYBLOCK_COLLECTIONS,
+        HypixelEndpoint::RESOURCES_SKYBLOCK_SKILLS,
+        HypixelEndpoint::RESOURCES_SKYBLOCK_ITEMS,
+        HypixelEndpoint::RESOURCES_SKYBLOCK_ELECTION,
+        HypixelEndpoint::RESOURCES_SKYBLOCK_BINGO,
+    ]
+    .iter()
+    .map(HypixelEndpoint::get_path)
+    .collect();
+}
+
+/// Whether requests to an endpoint path are sent with an API key
+fn requires_key(path: &str) -> bool {
+    !KEYLESS_PATHS.contains(path)
+}
+
+struct PooledClient {
+    key: String,
+    api: RsPixel,
+}
+
+/// A pool of `RsPixel` clients sharing the same response cache so that independent
+/// requests don't have to wait on each other while an upstream request is in flight.
+pub struct ApiPool {
+    idle: Mutex<Vec<PooledClient>>,
+    semaphore: Semaphore,
+    cache: Box<dyn CacheBackend>,
+    cache_ttls: ArcSwap<HashMap<String, Duration>>,
+    keys: KeyTracker,
+    /// What to do once the quota of every key is exhausted
+    rate_limit_strategy: RateLimit,
+}
+
+impl ApiPool {
+    /// Creates a pool from `(api_key, client)` pairs. Responses of endpoints with a TTL in
+    /// `cache_ttls` (keyed by endpoint path) are cached.
+    pub fn new(
+        clients: Vec<(String, RsPixel)>,
+        keys: KeyTracker,
+        cache: Box<dyn CacheBackend>,
+        cache_ttls: HashMap<String, Duration>,
+        rate_limit_strategy: RateLimit,
+    ) -> ApiPool {
+        ApiPool {
+            semaphore: Semaphore::new(clients.len()),
+            cache,
+            cache_ttls: ArcSwap::from_pointee(cache_ttls),
+            idle: Mutex::new(
+                clients
+                    .into_iter()
+                    .map(|(key, api)| PooledClient { key, api })
+                    .collect(),
+            ),
+            keys,
+            rate_limit_strategy,
+        }
+    }
+
+    /// Waits for an idle client and, for endpoints that `requires_key`, prefers the key with the most
+    /// remaining quota and reserves a request from it. The client is returned to the pool when the guard is dropped.
+    pub async fn client(&self, requires_key: bool) -> Result<PooledApi<'_>, Error> {
+        loop {
+            let permit = self.semaphore.acquire().await.unwrap();
+            if !requires_key {
+                let client = self.idle.lock().unwrap().pop().unwrap();
+                return Ok(PooledApi {
+                    pool: self,
+                    client: Some(client),
+                    _permit: permit,
+                });
+            }
+
+            let client = {
+                let mut idle = self.idle.lock().unwrap();
+                let best = idle
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, client)| self.keys.remaining(&client.key))
+                    .map(|(idx, _)| idx)
+                    .unwrap();
+                idle.swap_remove(best)
+            };
+
+            // Clients of evicted keys are dropped as long as another key can serve requests
+            if !self.keys.is_valid(&client.key) && self.keys.has_valid() {
+                permit.forget();
+                continue;
+            }
+
+            let reserved = self.keys.reserve(&client.key);
+            let pooled = PooledApi {
+                pool: self,
+                client: Some(client),
+                _permit: permit,
+            };
+            // The client with the most remaining quota is picked, so every idle key is exhausted
+            let time_till_reset = match reserved {
+                Ok(()) => return Ok(pooled),
+                Err(time_till_reset) => time_till_reset,
+            };
+            drop(pooled);
+            match self.rate_limit_strategy {
+                RateLimit::Delay => actix_web::rt::time::sleep(time_till_reset).await,
+                RateLimit::Error => {
+                    return Err(Error::RateLimit(time_till_reset.as_secs_f64().ceil() as i64))
+                }
+            }
+        }
+    }
+
+    pub fn set_cache_ttls(&self, cache_ttls: HashMap<String, Duration>) {
+        self.cache_ttls.store(Arc::new(cache_ttls));
+    }
+
+    /// Whether `fetch` would respond from the cache
+    pub async fn is_cached(&self, path: &str, params: &HashMap<String, String>) -> bool {
+        self.cache_ttls.load().contains_key(path)
+            && self
+                .cache
+                .contains(&cache_key(
+                    path,
+                    params.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+                ))
+                .await
+    }
+
+    /// Requests an endpoint through the response cache
+    pub async fn fetch<T>(
+        &self,
+        endpoint: HypixelEndpoint,
+        params: HashMap<String, String>,
+    ) -> Result<Arc<T>, Error>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let path = endpoint.get_path();
+        let key = cache_key(&path, params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let ttl = self.cache_ttls.load().get(&path).copied();
+
+        if ttl.is_some() {
+            if let Some(data) = self.cache.get(&key).await {
+                match serde_json::from_slice::<T>(&data) {
+                    Ok(cached) => {
+                        record_cache(&path, true);
+                        record_request_cache(true);
+                        return Ok(Arc::new(cached));
+                    }
+                    Err(err) => {
+                        warn!("Evicting cached response of {key} that can't be decoded: {err}");
+                        self.cache.remove(&key).await;
+                    }
+                }
+            }
+            record_cache(&path, false);
+            record_request_cache(false);
+        }
+
+        self.fetch_fresh(endpoint, params).await
+    }
+
+    /// Requests an endpoint without reading the response cache, the response is still cached
+    pub async fn fetch_fresh<T>(
+        &self,
+        endpoint: HypixelEndpoint,
+        params: HashMap<String, String>,
+    ) -> Result<Arc<T>, Error>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let path = endpoint.get_path();
+        let key = cache_key(&path, params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let ttl = self.cache_ttls.load().get(&path).copied();
+
+        let mut client = self.client(requires_key(&path)).await?;
+        let res = upstream(
+            Upstream::Hypixel.as_str(),
+            &path,
+            client.get::<Value>(endpoint, params),
+        )
+        .await;
+        drop(client);
+
+        // The upstream JSON is cached as is, so a cached response decodes like a fresh one
+        let res = res?;
+        let data = T::deserialize(&*res)?;
+        if let Some(ttl) = ttl {
+            self.cache
+                .insert(key, serde_json::to_vec(&*res)?, ttl)
+                .await;
+        }
+        Ok(Arc::new(data))
+    }
+
+    async fn fetch_by<T>(
+        &self,
+        endpoint: HypixelEndpoint,
+        key: &str,
+        value: &str,
+    ) -> Result<Arc<T>, Error>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let mut params = HashMap::new();
+        params.insert(key.to_string(), value.to_string());
+        self.fetch(endpoint, params).await
+    }
+
+    pub async fn get_boosters(&self) -> Result<Arc<BoostersResponse>, Error> {
+        self.fetch(HypixelEndpoint::BOOSTERS, HashMap::new()).await
+    }
+
+    pub async fn get_leaderboards(&self) -> Result<Arc<LeaderboardsResponse>, Error> {
+        self.fetch(HypixelEndpoint::LEADERBOARDS, HashMap::new())
+            .await
+    }
+
+    pub async fn get_punishment_stats(&self) -> Result<Arc<PunishmentStatsResponse>, Error> {
+        self.fetch(HypixelEndpoint::PUNISHMENT_STATS, HashMap::new())
+            .await
+    }
+
+    pub async fn get_player(&self, uuid: &str) -> Result<Arc<PlayerResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::PLAYER, "uuid", uuid).await
+    }
+
+    pub async fn get_guild_by_player(&self, player: &str) -> Result<Arc<GuildResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::GUILD, "player", player)
+            .await
+    }
+
+    pub async fn get_guild_by_name(&self, name: &str) -> Result<Arc<GuildResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::GUILD, "name", name).await
+    }
+
+    pub async fn get_guild_by_id(&self, id: &str) -> Result<Arc<GuildResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::GUILD, "id", id).await
+    }
+
+    pub async fn get_counts(&self) -> Result<Arc<CountsResponse>, Error> {
+        self.fetch(HypixelEndpoint::COUNTS, HashMap::new()).await
+    }
+
+    pub async fn get_status(&self, uuid: &str) -> Result<Arc<StatusResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::STATUS, "uuid", uuid).await
+    }
+
+    pub async fn get_recent_games(&self, uuid: &str) -> Result<Arc<RecentGamesResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::RECENT_GAMES, "uuid", uuid)
+            .await
+    }
+
+    pub async fn get_skyblock_profiles(
+        &self,
+        uuid: &str,
+    ) -> Result<Arc<SkyblockProfilesResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_PROFILES, "uuid", uuid)
+            .await
+    }
+
+    pub async fn get_skyblock_profile(
+        &self,
+        profile: &str,
+    ) -> Result<Arc<SkyblockProfileResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_PROFILE, "profile", profile)
+            .await
+    }
+
+    pub async fn get_skyblock_bingo(
+        &self,
+        uuid: &str,
+    ) -> Result<Arc<SkyblockBingoResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_BINGO, "uuid", uuid)
+            .await
+    }
+
+    pub async fn get_skyblock_news(&self) -> Result<Arc<SkyblockNewsResponse>, Error> {
+        self.fetch(HypixelEndpoint::SKYBLOCK_NEWS, HashMap::new())
+            .await
+    }
+
+    pub async fn get_skyblock_auction_by_uuid(
+        &self,
+        uuid: &str,
+    ) -> Result<Arc<SkyblockAuctionResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_AUCTION, "uuid", uuid)
+            .await
+    }
+
+    pub async fn get_skyblock_auction_by_player(
+        &self,
+        player: &str,
+    ) -> Result<Arc<SkyblockAuctionResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_AUCTION, "player", player)
+            .await
+    }
+
+    pub async fn get_skyblock_auction_by_profile(
+        &self,
+        profile: &str,
+    ) -> Result<Arc<SkyblockAuctionResponse>, Error> {
+        self.fetch_by(HypixelEndpoint::SKYBLOCK_AUCTION, "profile", profile)
+            .await
+    }
+
+    pub async fn get_skyblock_auctions(
+        &self,
+        page: i64,
+    ) -> Result<Arc<SkyblockAuctionsResponse>, Error> {
+        self.fetch_by(
+            HypixelEndpoint::SKYBLOCK_AUCTIONS,
+            "page",
+            &page.to_string(),
+        )
+        .await
+    }
+
+    /// Like `get_skyblock_auctions` but always requests the current page
+    pub async fn get_skyblock_auctions_fresh(
+        &self,
+        page: i64,
+    ) -> Result<Arc<SkyblockAuctionsResponse>, Error> {
+        let params = HashMap::from([("page".to_string(), page.to_string())]);
+        self.fetch_fresh(HypixelEndpoint::SKYBLOCK_AUCTIONS, params)
+            .await
+    }
+
+    pub async fn get_skyblock_auctions_ended(
+        &self,
+    ) -> Result<Arc<SkyblockAuctionsEndedResponse>, Error> {
+        self.fetch(HypixelEndpoint::SKYBLOCK_AUCTIONS_ENDED, HashMap::new())
+            .await
+    }
+
+    /// Like `get_skyblock_auctions_ended` but always requests the current auctions
+    pub async fn get_skyblock_auctions_ended_fresh(
+        &self,
+    ) -> Result<Arc<SkyblockAuctionsEndedResponse>, Error> {
+        self.fetch_fresh(HypixelEndpoint::SKYBLOCK_AUCTIONS_ENDED, HashMap::new())
+            .await
+    }
+
+    pub async fn get_skyblock_bazaar(&self) -> Result<Arc<SkyblockBazaarResponse>, Error> {
+        self.fetch(HypixelEndpoint::SKYBLOCK_BAZAAR, HashMap::new())
+            .await
+    }
+
+    /// Like `get_skyblock_bazaar` but always requests the current bazaar
+    pub async fn get_skyblock_bazaar_fresh(&self) -> Result<Arc<SkyblockBazaarResponse>, Error> {
+        self.fetch_fresh(HypixelEndpoint::SKYBLOCK_BAZAAR, HashMap::new())
+            .await
+    }
+
+    pub async fn get_skyblock_fire_sales(&self) -> Result<Arc<SkyblockFireSalesResponse>, Error> {
+        self.fetch(HypixelEndpoint::SKYBLOCK_FIRESALES, HashMap::new())
+            .await
+    }
+
+    pub async fn get_resources(&self, resource: HypixelEndpoint) -> Result<Arc<Value>, Error> {
+        if !resource.get_path().starts_with("resources/") {
+            Err(Error::UnknownResource)
+        } else {
+            self.fetch(resource, HashMap::new()).await
+        }
+    }
+
+    pub async fn ping_cache(&self) -> Result<(), String> {
+        self.cache.ping().await
+    }
+
+    pub fn key_health(&self) -> Vec<KeyHealth> {
+        self.keys.health()
+    }
+}
+
+/// Creates `API_POOL_SIZE` clients for every API key, keys that can't be validated are evicted
+pub async fn connect(
+    client: &surf::Client,
+    config: &Config,
+    keys: &KeyTracker,
+) -> Vec<(String, RsPixel)> {
+    let pool_size = config.api_pool_size;
+    let mut clients: Vec<(String, RsPixel)> = Vec::with_capacity(pool_size * config.api_keys.len());
+    for api_key in &config.api_keys {
+        let bootstrap_config = || -> RsPixelConfig {
+            ConfigBuilder::default()
+                .client(client.clone())
+                .add_hypixel_cache_ttl(HypixelEndpoint::KEY, Duration::from_secs(60))
+                .into()
+        };
+        let first = match RsPixel::from_config(api_key, bootstrap_config()).await {
+            Ok(first) => first,
+            Err(err) => {
+                warn!("Skipping API key {}: {err}", mask(api_key));
+                keys.evict(api_key);
+                continue;
+            }
+        };
+
+        // The key response of the first client is cached, so the others are created without a request
+        let mut key_clients = vec![first];
+        for _ in 1..pool_size {
+            let mut config = bootstrap_config();
+            config.cache = key_clients[0].config.cache.clone();
+            match RsPixel::from_config(api_key, config).await {
+                Ok(client) => key_clients.push(client),
+                Err(err) => {
+                    warn!(
+                        "Using {} clients for API key {}: {err}",
+                        key_clients.len(),
+                        mask(api_key)
+                    );
+                    break;
+                }
+            }
+        }
+        clients.extend(key_clients.into_iter().map(|c| (api_key.clone(), c)));
+    }
+
+    // Hypixel responses are cached by the pool and usernames by `Resolver`
+    for (_, client) in &mut clients {
+        client.config.cache = None;
+        client.config.hypixel_cache_ttls.clear();
+    }
+    clients
+}
+
+pub struct PooledApi<'a> {
+    pool: &'a ApiPool,
+    client: Option<PooledClient>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledApi<'_> {
+    type Target = RsPixel;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client.as_ref().unwrap().api
+    }
+}
+
+impl DerefMut for PooledApi<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client.as_mut().unwrap().api
+    }
+}
+
+impl Drop for PooledApi<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.idle.lock().unwrap().push(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use futures::future::join_all;
+    use serde_json::json;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    };
+
+    fn counts() -> MockResponse {
+        MockResponse::ok(json!({"success": true, "games": {}, "playerCount": 0}))
+    }
+
+    async fn get_counts(pool: &ApiPool) -> Result<Arc<CountsResponse>, Error> {
+        pool.fetch_fresh(HypixelEndpoint::COUNTS, HashMap::new())
+            .await
+    }
+
+    /// Time taken by 16 concurrent requests through `workers` clients, and the most that were in flight
+    async fn load(workers: usize) -> (Duration, usize) {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            counts().delay(Duration::from_millis(50)),
+        );
+        let pool = mock
+            .api_pool(&config(&format!("API_POOL_SIZE = {workers}")))
+            .await;
+
+        let start = Instant::now();
+        let results = join_all((0..16).map(|_| get_counts(&pool))).await;
+        assert!(results.iter().all(Result::is_ok));
+        (start.elapsed(), mock.max_in_flight())
+    }
+
+    #[actix_web::test]
+    async fn throughput_scales_with_workers() {
+        let (single, single_in_flight) = load(1).await;
+        let (pooled, pooled_in_flight) = load(8).await;
+
+        assert_eq!(single_in_flight, 1);
+        assert_eq!(pooled_in_flight, 8);
+        assert!(
+            single > pooled * 4,
+            "{single:?} with 1 worker, {pooled:?} with 8"
+        );
+    }
+
+    #[actix_web::test]
+    async fn boosters_are_served_from_the_cache() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/boosters",
+            MockResponse::ok(json!({
+                "success": true,
+                "boosters": [{
+                    "purchaserUuid": "069a79f444e94726a5befca90e38aaf5",
+                    "amount": 2.0,
+                    "originalLength": 3600,
+                    "length": 3595,
+                    "gameType": 51,
+                    "dateActivated": 1690000000000i64,
+                    "stacked": ["61699b2ed3274a019f1e0ea8c3f06bc6"]
+                }],
+                "boosterState": {"decrementing": true}
+            })),
+        );
+        let pool = mock
+            .api_pool(&config("[HYPIXEL_CACHE_TTL]\nBOOSTERS = 60"))
+            .await;
+
+        let fresh = pool.get_boosters().await.unwrap();
+        assert!(pool.is_cached("boosters", &HashMap::new()).await);
+        let cached = pool.get_boosters().await.unwrap();
+
+        assert_eq!(mock.hits("api.hypixel.net/boosters"), 1);
+        assert_eq!(
+            cached.boosters[0].stacked.get_stacked(),
+            fresh.boosters[0].stacked.get_stacked()
+        );
+    }
+
+    #[actix_web::test]
+    async fn undecodable_cache_entries_are_evicted() {
+        let mock = MockUpstream::start();
+        mock.respond("api.hypixel.net/counts", counts());
+        let pool = mock
+            .api_pool(&config("[HYPIXEL_CACHE_TTL]\nCOUNTS = 60"))
+            .await;
+        pool.cache
+            .insert(
+                "counts".to_string(),
+                b"{}".to_vec(),
+                Duration::from_secs(60),
+            )
+            .await;
+        assert!(pool.is_cached("counts", &HashMap::new()).await);
+
+        pool.get_counts().await.unwrap();
+        pool.get_counts().await.unwrap();
+
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 1);
+        assert!(pool.is_cached("counts", &HashMap::new()).await);
+    }
+
+    #[actix_web::test]
+    async fn pooled_clients_share_the_key_validation() {
+        let mock = MockUpstream::start();
+        let pool = mock.api_pool(&config("API_POOL_SIZE = 4")).await;
+
+        assert_eq!(pool.semaphore.available_permits(), 4);
+        assert_eq!(mock.hits("api.hypixel.net/key"), 1);
+    }
+
+    #[actix_web::test]
+    async fn quota_is_shared_by_the_clients_of_a_key() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            counts()
+                .header("RateLimit-Remaining", 0)
+                .header("RateLimit-Reset", 60),
+        );
+        let pool = mock
+            .api_pool(&config(
+                "API_POOL_SIZE = 4\nRATE_LIMIT_STRATEGY = \"Error\"",
+            ))
+            .await;
+
+        assert!(get_counts(&pool).await.is_ok());
+        assert!(matches!(get_counts(&pool).await, Err(Error::RateLimit(60))));
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 1);
+    }
+
+    #[actix_web::test]
+    async fn rate_limit_delay_does_not_block_the_worker() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            counts()
+                .header("RateLimit-Remaining", 0)
+                .header("RateLimit-Reset", 1),
+        );
+        let pool = mock
+            .api_pool(&config("RATE_LIMIT_STRATEGY = \"Delay\""))
+            .await;
+        get_counts(&pool).await.unwrap();
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = actix_web::rt::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        let start = Instant::now();
+        get_counts(&pool).await.unwrap();
+        ticker.abort();
+
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        assert!(ticks.load(Ordering::SeqCst) >= 20);
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 2);
+    }
+
+    fn auctions_page(page: i64) -> MockResponse {
+        MockResponse::ok(json!({
+            "success": true,
+            "page": page,
+            "totalPages": 2,
+            "totalAuctions": 0,
+            "lastUpdated": 1690000000000i64,
+            "auctions": []
+        }))
+    }
+
+    #[actix_web::test]
+    async fn keyless_endpoints_do_not_use_the_quota() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            counts()
+                .header("RateLimit-Remaining", 5)
+                .header("RateLimit-Reset", 60),
+        );
+        mock.respond("api.hypixel.net/skyblock/auctions?page=0", auctions_page(0));
+        mock.respond("api.hypixel.net/skyblock/auctions?page=1", auctions_page(1));
+        let web_data = mock.web_data(config("")).await;
+        get_counts(&web_data.api).await.unwrap();
+        assert_eq!(web_data.api.keys.remaining("test-key"), Some(5));
+
+        web_data.auction_house.refresh(&web_data).await.unwrap();
+
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 2);
+        assert_eq!(web_data.api.keys.remaining("test-key"), Some(5));
+    }
+
+    #[actix_web::test]
+    async fn keyless_endpoints_are_requested_once_the_quota_is_exhausted() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            counts()
+                .header("RateLimit-Remaining", 0)
+                .header("RateLimit-Reset", 60),
+        );
+        mock.respond("api.hypixel.net/skyblock/auctions?page=0", auctions_page(0));
+        let pool = mock
+            .api_pool(&config("RATE_LIMIT_STRATEGY = \"Error\""))
+            .await;
+        get_counts(&pool).await.unwrap();
+        assert!(matches!(get_counts(&pool).await, Err(Error::RateLimit(_))));
+
+        pool.get_skyblock_auctions_fresh(0).await.unwrap();
+        assert_eq!(pool.keys.remaining("test-key"), Some(0));
+    }
+
+    #[test]
+    fn only_public_endpoints_are_keyless() {
+        assert!(requires_key(&HypixelEndpoint::PLAYER.get_path()));
+        assert!(requires_key(&HypixelEndpoint::SKYBLOCK_AUCTION.get_path()));
+        assert!(!requires_key(
+            &HypixelEndpoint::SKYBLOCK_AUCTIONS.get_path()
+        ));
+        assert!(!requires_key(&HypixelEndpoint::SKYBLOCK_BAZAAR.get_path()));
+        assert!(!requires_key(
+            &HypixelEndpoint::RESOURCES_SKYBLOCK_ITEMS.get_path()
+        ));
+    }
+}
@@ -0,0 +1,551 @@
+use crate::{
+    error::WebError,
+    prices::item_key,
+    structs::{AuctionSearchQuery, WebData},
+    utils::bad_request,
+};
+use actix_web::{
+    get,
+    http::StatusCode,
+    web::{Data, Query},
+    HttpResponse, Responder, ResponseError,
+};
+use arc_swap::ArcSwapOption;
+use futures::{stream, StreamExt, TryStreamExt};
+use rs_pixel::{
+    response::skyblock::skyblock_auctions_response::{SkyblockAuction, SkyblockAuctionsResponse},
+    util::utils::get_timestamp_millis,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+/// Auction pages fetched at the same time while taking a snapshot
+const PAGE_CONCURRENCY: usize = 8;
+
+/// Auctions per page of `/skyblock/auctions/search`
+const SEARCH_PAGE_SIZE: usize = 100;
+
+/// Pulls restarted when the auction house updates in the middle of one
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotAuction {
+    pub uuid: String,
+    pub auctioneer: String,
+    /// `item_key` of the item
+    pub item: String,
+    pub item_name: String,
+    pub tier: String,
+    pub category: String,
+    pub count: u64,
+    pub bin: bool,
+    /// The BIN price, or the highest bid (the starting bid if there are none)
+    pub price: i64,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Every auction of the auction house when it was last updated
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    /// `lastUpdated` of the auction pages
+    pub last_updated: i64,
+    pub auctions: Vec<SnapshotAuction>,
+    /// Lowest BIN price of one item by item key
+    pub lowest_bin: HashMap<String, f64>,
+}
+
+impl Snapshot {
+    pub fn new<'a>(
+        last_updated: i64,
+        auctions: impl Iterator<Item = &'a SkyblockAuction>,
+    ) -> Snapshot {
+        let auctions = auctions
+            .filter_map(|auction| {
+                let item = auction.get_nbt()?.get(0)?.clone();
+                let price = auction
+                    .bids
+                    .iter()
+                    .map(|bid| bid.amount)
+                    .max()
+                    .filter(|_| !auction.bin)
+                    .unwrap_or(auction.starting_bid);
+
+                Some(SnapshotAuction {
+                    uuid: auction.uuid.clone(),
+                    auctioneer: auction.auctioneer.clone(),
+                    item: item_key(&item["tag"]["ExtraAttributes"])?,
+                    item_name: auction.item_name.clone(),
+                    tier: auction.tier.clone(),
+                    category: auction.category.clone(),
+                    count: item["Count"].as_u64().unwrap_or(1).max(1),
+                    bin: auction.bin,
+                    price,
+                    start: auction.start,
+                    end: auction.end,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut lowest_bin = HashMap::new();
+        for auction in auctions.iter().filter(|auction| auction.bin) {
+            let price = auction.price as f64 / auction.count as f64;
+            lowest_bin
+                .entry(auction.item.clone())
+                .and_modify(|lowest: &mut f64| *lowest = lowest.min(price))
+                .or_insert(price);
+        }
+
+        Snapshot {
+            last_updated,
+            auctions,
+            lowest_bin,
+        }
+    }
+}
+
+/// Snapshot of every auction page, taken again in the background when the auction house updates
+/// (about once a minute)
+pub struct AuctionHouse {
+    snapshot: ArcSwapOption<Snapshot>,
+    /// Where the snapshot is persisted, in `CACHE_DIR`
+    file: Option<PathBuf>,
+}
+
+impl AuctionHouse {
+    /// Loads the snapshot persisted in `cache_dir`, if any
+    pub fn new(cache_dir: Option<&Path>) -> AuctionHouse {
+        let file = cache_dir.map(|cache_dir| cache_dir.join("auctions.json"));
+        let snapshot =
+            file.as_deref()
+                .filter(|file| file.exists())
+                .and_then(|file| match fs::read(file) {
+                    Ok(data) => serde_json::from_slice::<Snapshot>(&data)
+                        .map_err(|err| warn!("Ignoring the persisted auction snapshot: {err}"))
+                        .ok(),
+                    Err(err) => {
+                        warn!("Unable to read the persisted auction snapshot: {err}");
+                        None
+                    }
+                });
+        if let Some(snapshot) = &snapshot {
+            info!("Loaded {} auctions", snapshot.auctions.len());
+        }
+
+        AuctionHouse {
+            snapshot: ArcSwapOption::from_pointee(snapshot),
+            file,
+        }
+    }
+
+    /// The last snapshot taken, without checking whether the auction house updated since
+    pub fn latest(&self) -> Option<Arc<Snapshot>> {
+        self.snapshot.load_full()
+    }
+
+    /// Takes a snapshot if the auction house updated since the last one
+    pub async fn refresh(&self, web_data: &WebData) -> Result<Arc<Snapshot>, WebError> {
+        let first_page = web_data
+            .api
+            .get_skyblock_auctions_fresh(0)
+            .await
+            .map_err(WebError::hypixel)?;
+        if let Some(snapshot) = self
+            .latest()
+            .filter(|snapshot| snapshot.last_updated >= first_page.last_updated)
+        {
+            return Ok(snapshot);
+        }
+
+        let pages = pull(web_data, first_page).await?;
+        let last_updated = pages[0].last_updated;
+        let snapshot = actix_web::rt::task::spawn_blocking(move || {
+            Snapshot::new(last_updated, pages.iter().flat_map(|page| &page.auctions))
+        })
+        .await
+        .map(Arc::new)
+        .map_err(|err| {
+            WebError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                err.to_string(),
+            )
+        })?;
+
+        self.snapshot.store(Some(snapshot.clone()));
+        self.persist(snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot in the background
+    fn persist(&self, snapshot: Arc<Snapshot>) {
+        let Some(file) = self.file.clone() else {
+            return;
+        };
+        actix_web::rt::task::spawn_blocking(move || {
+            // Written next to the file and renamed so that a crash doesn't leave a partial snapshot
+            let partial = file.with_extension("json.partial");
+            let res = serde_json::to_vec(&*snapshot)
+                .map_err(|err| err.to_string())
+                .and_then(|data| fs::write(&partial, data).map_err(|err| err.to_string()))
+                .and_then(|()| fs::rename(&partial, &file).map_err(|err| err.to_string()));
+            if let Err(err) = res {
+                error!("Unable to persist the auction snapshot: {err}");
+            }
+        });
+    }
+}
+
+/// Every auction page from the same update of the auction house as `first_page`,
+/// or from a later update if it changes in the meantime
+async fn pull(
+    web_data: &WebData,
+    mut first_page: Arc<SkyblockAuctionsResponse>,
+) -> Result<Vec<Arc<SkyblockAuctionsResponse>>, WebError> {
+    for _ in 0..MAX_ATTEMPTS {
+        let mut pages = stream::iter(1..first_page.total_pages)
+            .map(|page| web_data.api.get_skyblock_auctions_fresh(page))
+            .buffered(PAGE_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(WebError::hypixel)?;
+
+        if pages
+            .iter()
+            .all(|page| page.last_updated == first_page.last_updated)
+        {
+            pages.insert(0, first_page);
+            return Ok(pages);
+        }
+        first_page = web_data
+            .api
+            .get_skyblock_auctions_fresh(0)
+            .await
+            .map_err(WebError::hypixel)?;
+    }
+
+    Err(WebError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "auctions_unavailable",
+        "The auction house kept updating while it was being read",
+    ))
+}
+
+/// Takes a snapshot whenever the auction house updates, checking every `AUCTION_SNAPSHOT_INTERVAL` seconds
+pub async fn update_auctions(web_data: Data<WebData>, interval: Duration) {
+    loop {
+        if let Err(err) = web_data.auction_house.refresh(&web_data).await {
+            warn!("Unable to update the auction snapshot: {err}");
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+/// The last snapshot, requests never pull the auction house themselves since it takes about 60 pages
+fn latest_snapshot(web_data: &WebData) -> Result<Arc<Snapshot>, WebError> {
+    web_data.auction_house.latest().ok_or_else(|| {
+        let reason = if web_data.config.load().auction_snapshot_interval.is_some() {
+            "The first auction snapshot hasn't been taken yet"
+        } else {
+            "Auction snapshots aren't taken, set AUCTION_SNAPSHOT_INTERVAL to take them"
+        };
+        WebError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "snapshot_unavailable",
+            reason,
+        )
+    })
+}
+
+#[get("/skyblock/lowestbin")]
+pub async fn skyblock_lowest_bin(web_data: Data<WebData>) -> impl Responder {
+    match latest_snapshot(&web_data) {
+        Ok(snapshot) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "lastUpdated": snapshot.last_updated,
+            "items": snapshot.lowest_bin,
+        })),
+        Err(err) => err.error_response(),
+    }
+}
+
+/// Auctions of the snapshot that haven't ended, filtered by item key, type, and price
+#[get("/skyblock/auctions/search")]
+pub async fn skyblock_auctions_search(
+    web_data: Data<WebData>,
+    query: Query<AuctionSearchQuery>,
+) -> impl Responder {
+    let sort = match query.sort.as_deref().unwrap_or("price_asc") {
+        "price_asc" => Sort::PriceAsc,
+        "price_desc" => Sort::PriceDesc,
+        "ending_soon" => Sort::EndingSoon,
+        "newest" => Sort::Newest,
+        _ => return bad_request("Invalid sort provided"),
+    };
+    let snapshot = match latest_snapshot(&web_data) {
+        Ok(snapshot) => snapshot,
+        Err(err) => return err.error_response(),
+    };
+
+    // Keys of items with attributes start with their id, so `PET` matches every pet
+    let item = query.item.as_ref().map(|item| item.to_uppercase());
+    let now = get_timestamp_millis();
+    let mut auctions = snapshot
+        .auctions
+        .iter()
+        .filter(|auction| auction.end > now)
+        .filter(|auction| {
+            item.as_ref().is_none_or(|item| {
+                auction
+                    .item
+                    .strip_prefix(item.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(';'))
+            })
+        })
+        .filter(|auction| query.bin.is_none_or(|bin| auction.bin == bin))
+        .filter(|auction| query.max_price.is_none_or(|max| auction.price <= max))
+        .collect::<Vec<_>>();
+    match sort {
+        Sort::PriceAsc => auctions.sort_by_key(|auction| auction.price),
+        Sort::PriceDesc => auctions.sort_by_key(|auction| Reverse(auction.price)),
+        Sort::EndingSoon => auctions.sort_by_key(|auction| auction.end),
+        Sort::Newest => auctions.sort_by_key(|auction| Reverse(auction.start)),
+    }
+
+    let page = query.page.unwrap_or(0);
+    let total_auctions = auctions.len();
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "lastUpdated": snapshot.last_updated,
+        "page": page,
+        "totalPages": total_auctions.div_ceil(SEARCH_PAGE_SIZE),
+        "totalAuctions": total_auctions,
+        "auctions": auctions
+            .iter()
+            .skip(page.saturating_mul(SEARCH_PAGE_SIZE))
+            .take(SEARCH_PAGE_SIZE)
+            .collect::<Vec<_>>(),
+    }))
+}
+
+enum Sort {
+    PriceAsc,
+    PriceDesc,
+    EndingSoon,
+    Newest,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use serde_json::Value;
+
+    const SETTINGS: &str =
+        "[SERVER.ENDPOINT]\nSKYBLOCK_LOWEST_BIN = true\nSKYBLOCK_AUCTIONS_SEARCH = true";
+
+    /// Both pages of an auction house update, with every kind of priced item and one ended auction
+    const PAGES: &str = include_str!("../tests/fixtures/auctions.json");
+
+    const LAST_UPDATED: i64 = 1690000000000;
+
+    const PAGE_0: &str = "api.hypixel.net/skyblock/auctions?page=0";
+    const PAGE_1: &str = "api.hypixel.net/skyblock/auctions?page=1";
+
+    /// A page of the fixture as it was at `last_updated`
+    fn page(page: usize, last_updated: i64) -> MockResponse {
+        let mut page = serde_json::from_str::<Value>(PAGES).unwrap()[page].take();
+        page["lastUpdated"] = json!(last_updated);
+        MockResponse::ok(page)
+    }
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        mock.respond(PAGE_0, page(0, LAST_UPDATED));
+        mock.respond(PAGE_1, page(1, LAST_UPDATED));
+        mock
+    }
+
+    fn get(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn requests_wait_for_the_first_snapshot() {
+        let mock = MockUpstream::start();
+        let app = init_service(crate::app(mock.web_data(config(SETTINGS)).await)).await;
+
+        for uri in ["/skyblock/lowestbin", "/skyblock/auctions/search?item=PET"] {
+            let res = call_service(&app, get(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: Value = read_body_json(res).await;
+            assert_eq!(body["code"], "snapshot_unavailable");
+        }
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 0);
+    }
+
+    #[actix_web::test]
+    async fn requests_are_served_from_the_last_snapshot() {
+        let mock = MockUpstream::start();
+        let web_data = mock.web_data(config(SETTINGS)).await;
+        web_data
+            .auction_house
+            .snapshot
+            .store(Some(Arc::new(Snapshot {
+                last_updated: 1690000000000,
+                auctions: Vec::new(),
+                lowest_bin: HashMap::from([("HYPERION".to_string(), 900_000_000.0)]),
+            })));
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(&app, get("/skyblock/lowestbin").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["lastUpdated"], 1690000000000i64);
+        assert_eq!(body["items"]["HYPERION"], 900_000_000.0);
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 0);
+    }
+
+    #[actix_web::test]
+    async fn snapshots_have_every_page() {
+        let mock = mock();
+        let web_data = mock.web_data(config("")).await;
+
+        let snapshot = web_data.auction_house.refresh(&web_data).await.unwrap();
+        assert_eq!(snapshot.last_updated, LAST_UPDATED);
+        assert_eq!(snapshot.auctions.len(), 8);
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 2);
+        let hyperion = snapshot
+            .auctions
+            .iter()
+            .find(|auction| !auction.bin)
+            .unwrap();
+        assert_eq!(hyperion.item, "HYPERION");
+        // Auctions are priced by their highest bid
+        assert_eq!(hyperion.price, 600_000_000);
+    }
+
+    #[actix_web::test]
+    async fn lowest_bins_are_per_item() {
+        let mock = mock();
+        let web_data = mock.web_data(config("")).await;
+
+        let snapshot = web_data.auction_house.refresh(&web_data).await.unwrap();
+        assert_eq!(
+            snapshot.lowest_bin,
+            HashMap::from([
+                ("HYPERION".to_string(), 900_000_000.0),
+                // 64 for 51,200 is cheaper per item than 1 for 1,000
+                ("ENCHANTED_DIAMOND".to_string(), 800.0),
+                ("PET;BEE;EPIC".to_string(), 250_000.0),
+                ("PET;ENDER_DRAGON;LEGENDARY".to_string(), 500_000_000.0),
+                ("ENCHANTED_BOOK;ULTIMATE_WISE;5".to_string(), 1_000_000.0),
+            ])
+        );
+    }
+
+    #[actix_web::test]
+    async fn unchanged_auction_houses_are_not_pulled_again() {
+        let mock = mock();
+        let web_data = mock.web_data(config("")).await;
+
+        let first = web_data.auction_house.refresh(&web_data).await.unwrap();
+        let second = web_data.auction_house.refresh(&web_data).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 3);
+    }
+
+    #[actix_web::test]
+    async fn pulls_restart_when_the_auction_house_updates() {
+        let mock = MockUpstream::start();
+        let updated = LAST_UPDATED + 60_000;
+        mock.enqueue(PAGE_0, page(0, LAST_UPDATED));
+        mock.respond(PAGE_0, page(0, updated));
+        mock.respond(PAGE_1, page(1, updated));
+        let web_data = mock.web_data(config("")).await;
+
+        let snapshot = web_data.auction_house.refresh(&web_data).await.unwrap();
+        assert_eq!(snapshot.last_updated, updated);
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions"), 4);
+    }
+
+    #[actix_web::test]
+    async fn pulls_give_up_when_the_auction_house_keeps_updating() {
+        let mock = MockUpstream::start();
+        mock.respond(PAGE_0, page(0, LAST_UPDATED));
+        mock.respond(PAGE_1, page(1, LAST_UPDATED + 60_000));
+        let web_data = mock.web_data(config("")).await;
+
+        let Err(err) = web_data.auction_house.refresh(&web_data).await else {
+            panic!("a snapshot was taken");
+        };
+        assert_eq!(err.code, "auctions_unavailable");
+        assert!(web_data.auction_house.latest().is_none());
+        assert_eq!(
+            mock.hits("api.hypixel.net/skyblock/auctions"),
+            1 + 2 * MAX_ATTEMPTS
+        );
+    }
+
+    #[actix_web::test]
+    async fn search_filters_sorts_and_pages_active_auctions() {
+        let mock = mock();
+        let web_data = mock.web_data(config(SETTINGS)).await;
+        web_data.auction_house.refresh(&web_data).await.unwrap();
+        let app = init_service(crate::app(web_data)).await;
+        let search = |query: &str| {
+            let req = get(&format!("/skyblock/auctions/search{query}")).to_request();
+            let app = &app;
+            async move {
+                let res = call_service(app, req).await;
+                let status = res.status();
+                let body: Value = read_body_json(res).await;
+                (status, body)
+            }
+        };
+        let ids = |body: &Value| {
+            body["auctions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|auction| auction["uuid"].as_str().unwrap()[31..].to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        // The ended auction 7 is left out
+        let (_, body) = search("").await;
+        assert_eq!(ids(&body), "3,2,5,8,6,4,1");
+        assert_eq!(body["totalAuctions"], 7);
+        assert_eq!(body["totalPages"], 1);
+        assert_eq!(ids(&search("?sort=price_desc").await.1), "1,4,6,8,5,2,3");
+        assert_eq!(ids(&search("?sort=ending_soon").await.1), "4,6,1,5,3,2,8");
+        assert_eq!(ids(&search("?sort=newest").await.1), "4,2,3,5,1,6,8");
+
+        assert_eq!(ids(&search("?item=hyperion").await.1), "4,1");
+        assert_eq!(ids(&search("?item=HYPERION&bin=true").await.1), "1");
+        assert_eq!(ids(&search("?item=PET").await.1), "5,6");
+        assert_eq!(ids(&search("?item=PET;BEE").await.1), "5");
+        assert_eq!(ids(&search("?item=PE").await.1), "");
+        assert_eq!(ids(&search("?bin=false").await.1), "4");
+        assert_eq!(ids(&search("?max_price=250000").await.1), "3,2,5");
+
+        let (_, body) = search("?page=1").await;
+        assert_eq!(body["page"], 1);
+        assert_eq!(ids(&body), "");
+        assert_eq!(ids(&search(&format!("?page={}", usize::MAX)).await.1), "");
+        assert_eq!(search("?sort=cheapest").await.0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    client_ip::is_local,
+    config::Config,
+    error::WebError,
+    structs::{ClientKeyQuery, WebData},
+    utils::server_endpoint,
+};
+use actix_web::{
+    dev::ServiceRequest,
+    http::{header::HeaderName, StatusCode},
+    web::{self, Data},
+    HttpRequest,
+};
+
+const API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Endpoints that require an admin key once client keys are configured, and can only be used
+/// from the server itself until then
+const ADMIN_ENDPOINTS: [&str; 2] = ["KEY", "RELOAD"];
+
+/// Client key sent with the `X-Api-Key` header or the `key` query parameter
+pub fn client_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(API_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| {
+            web::Query::<ClientKeyQuery>::from_query(req.query_string())
+                .ok()?
+                .into_inner()
+                .key
+        })
+        .filter(|key| !key.is_empty())
+}
+
+pub enum Access {
+    Allowed,
+    /// The endpoint is disabled in the current config
+    Disabled,
+    Denied(WebError),
+}
+
+/// Whether the endpoint serving a request is enabled and can be used with its client key
+pub fn check(req: &ServiceRequest) -> Access {
+    // Probes and unknown paths don't need a key
+    let Some(endpoint) = server_endpoint(req.path()) else {
+        return Access::Allowed;
+    };
+    let config = req.app_data::<Data<WebData>>().unwrap().config.load();
+    if !config
+        .server
+        .endpoint
+        .get(endpoint)
+        .copied()
+        .unwrap_or(false)
+    {
+        return Access::Disabled;
+    }
+
+    let admin_endpoint = ADMIN_ENDPOINTS.contains(&endpoint);
+    match client_key(req.request()) {
+        Some(key) => match config.client_key(&key) {
+            None => denied(
+                StatusCode::UNAUTHORIZED,
+                "invalid_client_key",
+                "Invalid API key",
+            ),
+            Some(client)
+                if client
+                    .endpoints
+                    .as_ref()
+                    .is_some_and(|endpoints| !endpoints.contains(endpoint)) =>
+            {
+                denied(
+                    StatusCode::FORBIDDEN,
+                    "forbidden",
+                    "This API key cannot use this endpoint",
+                )
+            }
+            Some(client) if admin_endpoint && !client.admin => denied(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "This endpoint requires an admin API key",
+            ),
+            Some(_) => Access::Allowed,
+        },
+        None if config.client_keys_required
+            || (admin_endpoint && !config.client_keys.is_empty()) =>
+        {
+            denied(
+                StatusCode::UNAUTHORIZED,
+                "missing_client_key",
+                "Missing API key, set the X-Api-Key header",
+            )
+        }
+        None if admin_endpoint && !is_local(req.request()) => denied(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "This endpoint can only be used from the server itself until client keys are configured",
+        ),
+        None => Access::Allowed,
+    }
+}
+
+/// Whether an endpoint used on behalf of a request (like in a batch) is enabled and allowed for its client key
+pub fn is_allowed(config: &Config, client_key: Option<&str>, endpoint: &str) -> bool {
+    config
+        .server
+        .endpoint
+        .get(endpoint)
+        .copied()
+        .unwrap_or(false)
+        && client_key
+            .and_then(|key| config.client_key(key))
+            .and_then(|client| client.endpoints.as_ref())
+            .is_none_or(|endpoints| endpoints.contains(endpoint))
+}
+
+fn denied(status: StatusCode, code: &'static str, cause: &str) -> Access {
+    Access::Denied(WebError::new(status, code, cause))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockUpstream};
+    use actix_web::test::TestRequest;
+
+    const ENDPOINTS: &str = "[SERVER.ENDPOINT]\nKEY = true\nCOUNTS = true\nSTATUS = true\n";
+
+    const CLIENT_KEYS: &str = "[[CLIENT_KEYS]]\nKEY = \"admin-key\"\nADMIN = true\n\
+        [[CLIENT_KEYS]]\nKEY = \"user-key\"\n\
+        [[CLIENT_KEYS]]\nKEY = \"counts-key\"\nENDPOINTS = [\"COUNTS\"]\n";
+
+    fn get(uri: &str, peer: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr(format!("{peer}:5000").parse().unwrap())
+    }
+
+    /// Status and error code of a request, `None` if it is allowed
+    async fn send(settings: &str, req: TestRequest) -> Option<(StatusCode, &'static str)> {
+        let mock = MockUpstream::start();
+        let req = req
+            .app_data(mock.web_data(config(settings)).await)
+            .to_srv_request();
+        match check(&req) {
+            Access::Allowed => None,
+            Access::Disabled => Some((StatusCode::NOT_FOUND, "disabled")),
+            Access::Denied(err) => Some((err.status, err.code)),
+        }
+    }
+
+    #[actix_web::test]
+    async fn keys_can_use_their_endpoints() {
+        let settings = format!("{CLIENT_KEYS}{ENDPOINTS}");
+        let req = get("/counts", "1.2.3.4").insert_header(("X-Api-Key", "counts-key"));
+        assert_eq!(send(&settings, req).await, None);
+        let req = get("/counts?key=user-key", "1.2.3.4");
+        assert_eq!(send(&settings, req).await, None);
+    }
+
+    #[actix_web::test]
+    async fn keys_cannot_use_other_endpoints() {
+        let settings = format!("{CLIENT_KEYS}{ENDPOINTS}");
+        let req = get("/status?key=counts-key", "1.2.3.4");
+        assert_eq!(
+            send(&settings, req).await,
+            Some((StatusCode::FORBIDDEN, "forbidden"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn missing_and_unknown_keys_are_rejected() {
+        let settings = format!("CLIENT_KEYS_REQUIRED = true\n{CLIENT_KEYS}{ENDPOINTS}");
+        assert_eq!(
+            send(&settings, get("/counts", "1.2.3.4")).await,
+            Some((StatusCode::UNAUTHORIZED, "missing_client_key"))
+        );
+        assert_eq!(
+            send(&settings, get("/counts?key=other-key", "1.2.3.4")).await,
+            Some((StatusCode::UNAUTHORIZED, "invalid_client_key"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn admin_endpoints_require_an_admin_key() {
+        let settings = format!("{CLIENT_KEYS}{ENDPOINTS}");
+        assert_eq!(
+            send(&settings, get("/key?key=user-key", "1.2.3.4")).await,
+            Some((StatusCode::FORBIDDEN, "forbidden"))
+        );
+        assert_eq!(
+            send(&settings, get("/key", "127.0.0.1")).await,
+            Some((StatusCode::UNAUTHORIZED, "missing_client_key"))
+        );
+        let req = get("/key?key=admin-key", "1.2.3.4");
+        assert_eq!(send(&settings, req).await, None);
+    }
+
+    #[actix_web::test]
+    async fn admin_endpoints_are_local_without_client_keys() {
+        assert_eq!(
+            send(ENDPOINTS, get("/key", "1.2.3.4")).await,
+            Some((StatusCode::FORBIDDEN, "forbidden"))
+        );
+        let req = get("/key", "127.0.0.1").insert_header(("X-Forwarded-For", "1.2.3.4"));
+        assert_eq!(
+            send(ENDPOINTS, req).await,
+            Some((StatusCode::FORBIDDEN, "forbidden"))
+        );
+        assert_eq!(send(ENDPOINTS, get("/key", "127.0.0.1")).await, None);
+        assert_eq!(send(ENDPOINTS, get("/counts", "1.2.3.4")).await, None);
+        assert_eq!(
+            send("", get("/counts", "1.2.3.4")).await,
+            Some((StatusCode::NOT_FOUND, "disabled"))
+        );
+    }
+}
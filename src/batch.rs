@@ -0,0 +1,204 @@
+use crate::{
+    auth::{client_key, is_allowed},
+    config::Config,
+    endpoints::hypixel_request,
+    error::WebError,
+    extractors::{cached_player, resolve_player},
+    rate_limit::charge,
+    structs::{BatchOperation, BatchResult, WebData},
+};
+use actix_web::{
+    http::StatusCode,
+    post,
+    web::{Data, Json},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use futures::{future::join_all, stream, StreamExt};
+use rs_pixel::util::error::Error;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Endpoints that can be used in a batch and the server endpoint each one runs as
+const OPERATIONS: [(&str, &str); 6] = [
+    ("player", "PLAYER"),
+    ("status", "STATUS"),
+    ("recentGames", "RECENT_GAMES"),
+    ("guild", "GUILD"),
+    ("skyblock/profiles", "SKYBLOCK_PROFILES"),
+    ("skyblock/bingo", "SKYBLOCK_BINGO"),
+];
+
+/// Runs a list of player lookups, `BATCH_CONCURRENCY` at a time, and responds with their results in order
+#[post("/batch")]
+pub async fn batch(
+    web_data: Data<WebData>,
+    req: HttpRequest,
+    operations: Json<Vec<BatchOperation>>,
+) -> impl Responder {
+    let config = web_data.config.load_full();
+    if operations.len() > config.batch_max_size {
+        return WebError::bad_request(format!(
+            "A batch can have at most {} operations",
+            config.batch_max_size
+        ))
+        .error_response();
+    }
+    // The batch took one request from the quota of the client, every other lookup that isn't cached takes one more
+    let uncached = join_all(
+        operations
+            .iter()
+            .map(|operation| is_cached(&web_data, operation)),
+    )
+    .await
+    .into_iter()
+    .filter(|cached| !cached)
+    .count();
+    if let Err(err) = charge(&web_data, &req, uncached.saturating_sub(1)) {
+        return err.error_response();
+    }
+    let client_key = client_key(&req);
+
+    let results = stream::iter(operations.into_inner())
+        .map(|operation| run(&web_data, &config, client_key.as_deref(), operation))
+        .buffered(config.batch_concurrency)
+        .map(|res| match res {
+            Ok(response) => BatchResult {
+                status: StatusCode::OK.as_u16(),
+                response,
+            },
+            Err(err) => BatchResult {
+                status: err.status.as_u16(),
+                response: json!(err),
+            },
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    HttpResponse::Ok().json(json!({ "success": true, "results": results }))
+}
+
+async fn run(
+    web_data: &WebData,
+    config: &Config,
+    client_key: Option<&str>,
+    operation: BatchOperation,
+) -> Result<Value, WebError> {
+    let Some((_, endpoint)) = OPERATIONS
+        .iter()
+        .find(|(name, _)| *name == operation.endpoint)
+    else {
+        return Err(WebError::bad_request(format!(
+            "Unknown endpoint provided, expected one of [{}]",
+            OPERATIONS.map(|(name, _)| name).join(", ")
+        )));
+    };
+    if !is_allowed(config, client_key, endpoint) {
+        return Err(WebError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "This endpoint is disabled or cannot be used with this API key",
+        ));
+    }
+
+    let uuid = resolve_player(web_data, &operation.player)
+        .await?
+        .ok_or_else(|| {
+            WebError::bad_request("Missing one or more fields [username, uuid, player]")
+        })?
+        .uuid;
+    let api = &web_data.api;
+    match *endpoint {
+        "PLAYER" => to_value(api.get_player(&uuid).await),
+        "STATUS" => to_value(api.get_status(&uuid).await),
+        "RECENT_GAMES" => to_value(api.get_recent_games(&uuid).await),
+        "GUILD" => to_value(api.get_guild_by_player(&uuid).await),
+        "SKYBLOCK_PROFILES" => to_value(api.get_skyblock_profiles(&uuid).await),
+        "SKYBLOCK_BINGO" => to_value(api.get_skyblock_bingo(&uuid).await),
+        _ => unreachable!(),
+    }
+}
+
+/// Whether an operation is answered from the cache, operations with an unknown endpoint fail without a lookup
+async fn is_cached(web_data: &WebData, operation: &BatchOperation) -> bool {
+    if !OPERATIONS
+        .iter()
+        .any(|(name, _)| *name == operation.endpoint)
+    {
+        return true;
+    }
+
+    let Some(uuid) = cached_player(web_data, &operation.player) else {
+        return false;
+    };
+    match hypixel_request(
+        web_data,
+        &format!("/{}", operation.endpoint),
+        &format!("uuid={uuid}"),
+    ) {
+        Some((path, params)) => web_data.api.is_cached(&path, &params).await,
+        None => false,
+    }
+}
+
+fn to_value<T: Serialize>(res: Result<Arc<T>, Error>) -> Result<Value, WebError> {
+    res.and_then(|res| Ok(serde_json::to_value(&*res)?))
+        .map_err(WebError::hypixel)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+    };
+    use serde_json::{json, Value};
+
+    const SETTINGS: &str =
+        "[SERVER]\nPERIOD = 60000\nBURST = 3\n[SERVER.ENDPOINT]\nBATCH = true\nPLAYER = true";
+
+    fn batch(size: usize) -> TestRequest {
+        let operations = (0..size)
+            .map(|idx| json!({"endpoint": "player", "uuid": format!("{idx:032x}")}))
+            .collect::<Vec<_>>();
+        TestRequest::post()
+            .uri("/batch")
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+            .set_json(operations)
+    }
+
+    #[actix_web::test]
+    async fn operations_take_from_the_quota_before_running() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/player",
+            MockResponse::ok(json!({"success": true, "player": {}})),
+        );
+        let web_data = mock.web_data(config(SETTINGS)).await;
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(&app, batch(4).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["code"], "too_many_requests");
+        assert_eq!(mock.hits("api.hypixel.net/player"), 0);
+
+        let res = call_service(&app, batch(2).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "1");
+        assert_eq!(mock.hits("api.hypixel.net/player"), 2);
+    }
+
+    #[actix_web::test]
+    async fn batches_larger_than_the_burst_are_rejected() {
+        let mock = MockUpstream::start();
+        let web_data = mock.web_data(config(SETTINGS)).await;
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(&app, batch(5).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let res = call_service(&app, batch(1).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
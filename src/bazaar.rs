@@ -0,0 +1,505 @@
+use crate::{
+    config::Config,
+    error::WebError,
+    structs::{BazaarHistoryQuery, WebData},
+    utils::bad_request,
+};
+use actix_web::{
+    get,
+    http::StatusCode,
+    web::{Data, Path, Query},
+    HttpResponse, Responder, ResponseError,
+};
+use rs_pixel::util::utils::get_timestamp_millis;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    error::Error,
+    fmt::Write,
+    fs,
+    path::Path as FilePath,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::warn;
+
+const MINUTE_MILLIS: i64 = 60 * 1000;
+const HOUR_MILLIS: i64 = 60 * MINUTE_MILLIS;
+const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+
+/// Resolutions samples are kept at, each one is downsampled from the previous one
+const RESOLUTIONS: [(&str, i64); 3] = [
+    ("1m", MINUTE_MILLIS),
+    ("1h", HOUR_MILLIS),
+    ("1d", DAY_MILLIS),
+];
+
+/// Prices and volumes of a product, averaged over the resolution of the sample
+#[derive(Serialize, Debug, PartialEq)]
+struct Sample {
+    /// Start of the sample
+    timestamp: i64,
+    buy_price: f64,
+    sell_price: f64,
+    buy_volume: f64,
+    sell_volume: f64,
+}
+
+/// Bazaar prices sampled into a SQLite database and downsampled to hourly and daily averages
+pub struct BazaarHistory {
+    connection: Arc<Mutex<Connection>>,
+    /// `lastUpdated` of the last bazaar that was sampled
+    last_updated: AtomicI64,
+}
+
+impl BazaarHistory {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: &FilePath) -> Result<BazaarHistory, Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS bazaar_history (
+                product_id TEXT NOT NULL,
+                resolution INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                buy_price REAL NOT NULL,
+                sell_price REAL NOT NULL,
+                buy_volume REAL NOT NULL,
+                sell_volume REAL NOT NULL,
+                PRIMARY KEY (product_id, resolution, timestamp)
+            );",
+        )?;
+
+        Ok(BazaarHistory {
+            connection: Arc::new(Mutex::new(connection)),
+            last_updated: AtomicI64::new(0),
+        })
+    }
+
+    /// Samples the bazaar if it updated since the last sample, updates the averages of
+    /// the current hour and day, and deletes samples past their retention
+    async fn sample(&self, web_data: &WebData) -> Result<(), String> {
+        let res = web_data
+            .api
+            .get_skyblock_bazaar_fresh()
+            .await
+            .map_err(|err| err.to_string())?;
+        if self.last_updated.load(Ordering::Relaxed) == res.last_updated {
+            return Ok(());
+        }
+
+        let retention = retention(&web_data.config.load());
+        let connection = self.connection.clone();
+        let last_updated = res.last_updated;
+        actix_web::rt::task::spawn_blocking(move || {
+            // Samples of the same minute replace each other
+            let timestamp = last_updated - last_updated % MINUTE_MILLIS;
+            let samples = res
+                .products
+                .iter()
+                .map(|(product_id, product)| {
+                    let status = &product.quick_status;
+                    let sample = Sample {
+                        timestamp,
+                        buy_price: status.buy_price,
+                        sell_price: status.sell_price,
+                        buy_volume: status.buy_volume as f64,
+                        sell_volume: status.sell_volume as f64,
+                    };
+                    (product_id.as_str(), sample)
+                })
+                .collect::<Vec<_>>();
+            record(
+                &mut connection.lock().unwrap(),
+                &samples,
+                get_timestamp_millis(),
+                retention,
+            )
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+        self.last_updated.store(last_updated, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn samples(
+        &self,
+        product_id: String,
+        resolution: i64,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Sample>, String> {
+        let connection = self.connection.clone();
+        actix_web::rt::task::spawn_blocking(move || {
+            query(
+                &connection.lock().unwrap(),
+                &product_id,
+                resolution,
+                from,
+                to,
+            )
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+    }
+}
+
+/// Inserts minute samples, updates the averages of the hour and day of the samples, and deletes
+/// samples past their `retention`
+fn record(
+    connection: &mut Connection,
+    samples: &[(&str, Sample)],
+    now: i64,
+    retention: [Option<i64>; 3],
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT OR REPLACE INTO bazaar_history
+            (product_id, resolution, timestamp, buy_price, sell_price, buy_volume, sell_volume)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for (product_id, sample) in samples {
+            insert.execute(params![
+                product_id,
+                MINUTE_MILLIS,
+                sample.timestamp,
+                sample.buy_price,
+                sample.sell_price,
+                sample.buy_volume,
+                sample.sell_volume
+            ])?;
+        }
+    }
+
+    if let Some(timestamp) = samples.iter().map(|(_, sample)| sample.timestamp).min() {
+        for window in RESOLUTIONS.windows(2) {
+            let ((_, finer), (_, resolution)) = (window[0], window[1]);
+            transaction.execute(
+                "INSERT OR REPLACE INTO bazaar_history
+                SELECT product_id, ?2, timestamp / ?2 * ?2,
+                    AVG(buy_price), AVG(sell_price), AVG(buy_volume), AVG(sell_volume)
+                FROM bazaar_history
+                WHERE resolution = ?1 AND timestamp >= ?3
+                GROUP BY product_id, timestamp / ?2",
+                params![finer, resolution, timestamp - timestamp % resolution],
+            )?;
+        }
+    }
+
+    for ((_, resolution), retention) in RESOLUTIONS.iter().zip(retention) {
+        if let Some(retention) = retention {
+            transaction.execute(
+                "DELETE FROM bazaar_history WHERE resolution = ?1 AND timestamp < ?2",
+                params![resolution, now - retention],
+            )?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Samples of a product at a resolution between `from` and `to`, oldest first
+fn query(
+    connection: &Connection,
+    product_id: &str,
+    resolution: i64,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<Sample>> {
+    connection
+        .prepare_cached(
+            "SELECT timestamp, buy_price, sell_price, buy_volume, sell_volume
+            FROM bazaar_history
+            WHERE product_id = ?1 AND resolution = ?2 AND timestamp >= ?3 AND timestamp <= ?4
+            ORDER BY timestamp",
+        )?
+        .query_map(params![product_id, resolution, from, to], |row| {
+            Ok(Sample {
+                timestamp: row.get(0)?,
+                buy_price: row.get(1)?,
+                sell_price: row.get(2)?,
+                buy_volume: row.get(3)?,
+                sell_volume: row.get(4)?,
+            })
+        })?
+        .collect()
+}
+
+/// The finest resolution that is kept for samples taken `since` milliseconds ago
+fn default_resolution(
+    since: i64,
+    retention: [Option<i64>; 3],
+) -> Option<&'static (&'static str, i64)> {
+    RESOLUTIONS
+        .iter()
+        .zip(retention)
+        .find(|(_, retention)| retention.is_none_or(|retention| retention >= since))
+        .map(|(resolution, _)| resolution)
+}
+
+fn to_csv(samples: &[Sample]) -> String {
+    let mut body = String::from("timestamp,buy_price,sell_price,buy_volume,sell_volume\n");
+    for sample in samples {
+        let _ = writeln!(
+            body,
+            "{},{},{},{},{}",
+            sample.timestamp,
+            sample.buy_price,
+            sample.sell_price,
+            sample.buy_volume,
+            sample.sell_volume
+        );
+    }
+    body
+}
+
+/// Milliseconds samples of each resolution are kept for, `None` to keep them
+fn retention(config: &Config) -> [Option<i64>; 3] {
+    [
+        Some(config.bazaar_retention_minute),
+        Some(config.bazaar_retention_hour),
+        config.bazaar_retention_day,
+    ]
+    .map(|days| days.map(|days| days as i64 * DAY_MILLIS))
+}
+
+/// Samples the bazaar every `BAZAAR_SAMPLE_INTERVAL` seconds
+pub async fn record_bazaar(web_data: Data<WebData>, interval: Duration) {
+    let Some(history) = &web_data.bazaar_history else {
+        return;
+    };
+    loop {
+        if let Err(err) = history.sample(&web_data).await {
+            warn!("Unable to sample the bazaar: {err}");
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+/// Price and volume history of a bazaar product as JSON or CSV
+#[get("/skyblock/bazaar/history/{product_id}")]
+pub async fn skyblock_bazaar_history(
+    web_data: Data<WebData>,
+    product_id: Path<String>,
+    query: Query<BazaarHistoryQuery>,
+) -> impl Responder {
+    let Some(history) = &web_data.bazaar_history else {
+        return WebError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "bazaar_history_unavailable",
+            "The bazaar isn't recorded, set BAZAAR_DB to record it",
+        )
+        .error_response();
+    };
+    let csv = match query.format.as_deref().unwrap_or("json") {
+        "json" => false,
+        "csv" => true,
+        _ => return bad_request("Invalid format provided"),
+    };
+    let to = query.to.unwrap_or_else(get_timestamp_millis);
+    let from = query.from.unwrap_or(to - DAY_MILLIS);
+    if from > to {
+        return bad_request("from must be before to");
+    }
+    // Defaults to the finest resolution that is kept for the whole range
+    let resolution = match query.resolution.as_deref() {
+        Some(resolution) => RESOLUTIONS.iter().find(|(name, _)| *name == resolution),
+        None => default_resolution(
+            get_timestamp_millis() - from,
+            retention(&web_data.config.load()),
+        ),
+    };
+    let Some((resolution_name, resolution)) = resolution else {
+        return bad_request("Invalid resolution provided");
+    };
+
+    let product_id = product_id.into_inner().to_uppercase();
+    let samples = match history
+        .samples(product_id.clone(), *resolution, from, to)
+        .await
+    {
+        Ok(samples) => samples,
+        Err(err) => {
+            warn!("Unable to read the bazaar history: {err}");
+            return WebError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Unable to read the bazaar history",
+            )
+            .error_response();
+        }
+    };
+
+    if csv {
+        let body = to_csv(&samples);
+        return HttpResponse::Ok().content_type("text/csv").body(body);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "product_id": product_id,
+        "resolution": resolution_name,
+        "from": from,
+        "to": to,
+        "history": samples,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockUpstream};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+
+    /// An hour into the 10th day since the epoch
+    const HOUR: i64 = 10 * DAY_MILLIS + 3 * HOUR_MILLIS;
+    const RETENTION: [Option<i64>; 3] = [Some(DAY_MILLIS), Some(7 * DAY_MILLIS), None];
+
+    fn sample(timestamp: i64, buy_price: f64) -> Sample {
+        Sample {
+            timestamp,
+            buy_price,
+            sell_price: buy_price - 1.0,
+            buy_volume: 1000.0,
+            sell_volume: 2000.0,
+        }
+    }
+
+    fn history() -> BazaarHistory {
+        BazaarHistory::open(FilePath::new(":memory:")).unwrap()
+    }
+
+    fn record_at(history: &BazaarHistory, timestamp: i64, buy_price: f64, now: i64) {
+        let samples = [("ENCHANTED_DIAMOND", sample(timestamp, buy_price))];
+        record(
+            &mut history.connection.lock().unwrap(),
+            &samples,
+            now,
+            RETENTION,
+        )
+        .unwrap();
+    }
+
+    fn buy_prices(history: &BazaarHistory, resolution: i64) -> Vec<(i64, f64)> {
+        query(
+            &history.connection.lock().unwrap(),
+            "ENCHANTED_DIAMOND",
+            resolution,
+            0,
+            i64::MAX,
+        )
+        .unwrap()
+        .iter()
+        .map(|sample| (sample.timestamp, sample.buy_price))
+        .collect()
+    }
+
+    #[test]
+    fn the_current_hour_is_averaged_from_its_minutes_so_far() {
+        let history = history();
+        record_at(&history, HOUR, 10.0, HOUR);
+        assert_eq!(buy_prices(&history, HOUR_MILLIS), [(HOUR, 10.0)]);
+
+        record_at(&history, HOUR + MINUTE_MILLIS, 20.0, HOUR);
+        assert_eq!(buy_prices(&history, HOUR_MILLIS), [(HOUR, 15.0)]);
+        assert_eq!(buy_prices(&history, DAY_MILLIS), [(10 * DAY_MILLIS, 15.0)]);
+
+        // Previous hours are left as they were, days average their hours
+        record_at(&history, HOUR + HOUR_MILLIS, 40.0, HOUR);
+        assert_eq!(
+            buy_prices(&history, HOUR_MILLIS),
+            [(HOUR, 15.0), (HOUR + HOUR_MILLIS, 40.0)]
+        );
+        assert_eq!(buy_prices(&history, DAY_MILLIS), [(10 * DAY_MILLIS, 27.5)]);
+    }
+
+    #[test]
+    fn samples_of_the_same_minute_replace_each_other() {
+        let history = history();
+        record_at(&history, HOUR, 10.0, HOUR);
+        record_at(&history, HOUR, 30.0, HOUR);
+        assert_eq!(buy_prices(&history, MINUTE_MILLIS), [(HOUR, 30.0)]);
+        assert_eq!(buy_prices(&history, HOUR_MILLIS), [(HOUR, 30.0)]);
+    }
+
+    #[test]
+    fn samples_are_deleted_past_the_retention_of_their_resolution() {
+        let history = history();
+        record_at(&history, HOUR, 10.0, HOUR);
+
+        let now = HOUR + 2 * DAY_MILLIS;
+        record_at(&history, now, 20.0, now);
+        assert_eq!(buy_prices(&history, MINUTE_MILLIS), [(now, 20.0)]);
+        assert_eq!(buy_prices(&history, HOUR_MILLIS).len(), 2);
+
+        let later = HOUR + 8 * DAY_MILLIS;
+        record_at(&history, later, 30.0, later);
+        assert_eq!(
+            buy_prices(&history, HOUR_MILLIS),
+            [(now, 20.0), (later, 30.0)]
+        );
+        assert_eq!(buy_prices(&history, DAY_MILLIS).len(), 3);
+    }
+
+    #[test]
+    fn the_default_resolution_is_kept_for_the_whole_range() {
+        let name = |since| default_resolution(since, RETENTION).map(|(name, _)| *name);
+        assert_eq!(name(HOUR_MILLIS), Some("1m"));
+        assert_eq!(name(DAY_MILLIS), Some("1m"));
+        assert_eq!(name(DAY_MILLIS + 1), Some("1h"));
+        assert_eq!(name(30 * DAY_MILLIS), Some("1d"));
+
+        let retention = [
+            Some(DAY_MILLIS),
+            Some(7 * DAY_MILLIS),
+            Some(30 * DAY_MILLIS),
+        ];
+        assert_eq!(default_resolution(31 * DAY_MILLIS, retention), None);
+    }
+
+    #[actix_web::test]
+    async fn history_is_served_as_csv() {
+        let mock = MockUpstream::start();
+        let web_data = mock
+            .web_data(config("[SERVER.ENDPOINT]\nSKYBLOCK_BAZAAR_HISTORY = true"))
+            .await;
+        let mut web_data = Arc::into_inner(web_data.into_inner()).unwrap();
+        let history = history();
+        record_at(&history, HOUR, 10.5, HOUR);
+        record_at(&history, HOUR + MINUTE_MILLIS, 20.0, HOUR);
+        web_data.bazaar_history = Some(history);
+        let app = init_service(crate::app(Data::new(web_data))).await;
+
+        let req = TestRequest::get()
+            .uri(&format!(
+                "/skyblock/bazaar/history/enchanted_diamond?format=csv&resolution=1m&from={HOUR}&to={}",
+                HOUR + HOUR_MILLIS
+            ))
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("content-type").unwrap(), "text/csv");
+        let body = read_body(res).await;
+        assert_eq!(
+            body,
+            format!(
+                "timestamp,buy_price,sell_price,buy_volume,sell_volume\n\
+                {HOUR},10.5,9.5,1000,2000\n\
+                {},20,19,1000,2000\n",
+                HOUR + MINUTE_MILLIS
+            )
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use async_trait::async_trait;
+use moka::{future::Cache, Expiry};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use rs_pixel::util::utils::get_timestamp_millis;
+use rusqlite::{params, Connection};
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+#[derive(Clone)]
+struct CacheEntry {
+    ttl: Duration,
+    data: Arc<Vec<u8>>,
+}
+
+struct CacheEntryExpiry;
+
+impl Expiry<String, CacheEntry> for CacheEntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        value: &CacheEntry,
+        _current_time: Instant,
+    ) -> Option<Duration> {
+        Some(value.ttl)
+    }
+}
+
+/// Storage for serialized Hypixel API responses
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Arc<Vec<u8>>>;
+
+    async fn insert(&self, key: String, data: Vec<u8>, ttl: Duration);
+
+    async fn remove(&self, key: &str);
+
+    /// Whether a key is cached, without reading its data. Used by the rate limiter.
+    async fn contains(&self, key: &str) -> bool;
+
+    /// Checks that the backing storage can be reached
+    async fn ping(&self) -> Result<(), String>;
+}
+
+/// A change to the persistent cache
+enum Write {
+    Insert {
+        key: String,
+        expires_at: i64,
+        data: Arc<Vec<u8>>,
+    },
+    Remove(String),
+    /// Answered once every earlier write is done
+    Ping(oneshot::Sender<Result<(), String>>),
+}
+
+/// Applies writes to the persistent cache in the order they were made, until the cache is dropped
+fn write_persistent(connection: Connection, writes: mpsc::Receiver<Write>) {
+    for write in writes {
+        match write {
+            Write::Insert {
+                key,
+                expires_at,
+                data,
+            } => {
+                if let Err(err) = connection.execute(
+                    "DELETE FROM cache WHERE expires_at <= ?1",
+                    params![get_timestamp_millis()],
+                ) {
+                    error!("Unable to clean up the persistent cache: {err}");
+                }
+                if let Err(err) = connection.execute(
+                    "INSERT OR REPLACE INTO cache (key, expires_at, data) VALUES (?1, ?2, ?3)",
+                    params![key, expires_at, data.as_slice()],
+                ) {
+                    error!("Unable to write to the persistent cache: {err}");
+                }
+            }
+            Write::Remove(key) => {
+                if let Err(err) =
+                    connection.execute("DELETE FROM cache WHERE key = ?1", params![key])
+                {
+                    error!("Unable to delete from the persistent cache: {err}");
+                }
+            }
+            Write::Ping(res) => {
+                let _ = res.send(
+                    connection
+                        .query_row("SELECT 1", [], |_| Ok(()))
+                        .map_err(|err| err.to_string()),
+                );
+            }
+        }
+    }
+}
+
+/// In-memory cache, optionally persisted to a SQLite file so that a restart
+/// doesn't start with a cold cache.
+pub struct MemoryCache {
+    memory: Cache<String, CacheEntry>,
+    /// Writes are made by a single thread so a slow disk doesn't delay responses, and so that
+    /// a removal can't overtake the insert it undoes
+    store: Option<mpsc::Sender<Write>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> MemoryCache {
+        MemoryCache {
+            memory: Cache::builder().expire_after(CacheEntryExpiry).build(),
+            store: None,
+        }
+    }
+
+    /// Opens (or creates) the cache file in `cache_dir` and loads every unexpired entry
+    pub async fn persistent(cache_dir: &Path) -> Result<MemoryCache, Box<dyn Error>> {
+        fs::create_dir_all(cache_dir)?;
+        let connection = Connection::open(cache_dir.join("cache.sqlite"))?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS cache (
+                key TEXT PRIMARY KEY,
+                expires_at INTEGER NOT NULL,
+                data BLOB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS cache_expires_at ON cache (expires_at);",
+        )?;
+
+        let now = get_timestamp_millis();
+        connection.execute("DELETE FROM cache WHERE expires_at <= ?1", params![now])?;
+
+        let cache = MemoryCache::new();
+        let entries = connection
+            .prepare("SELECT key, expires_at, data FROM cache")?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        info!("Loaded {} cached responses", entries.len());
+        for (key, expires_at, data) in entries {
+            let entry = CacheEntry {
+                ttl: Duration::from_millis((expires_at - now) as u64),
+                data: Arc::new(data),
+            };
+            cache.memory.insert(key, entry).await;
+        }
+
+        let (store, writes) = mpsc::channel();
+        thread::Builder::new()
+            .name("cache-writer".to_string())
+            .spawn(move || write_persistent(connection, writes))?;
+        Ok(MemoryCache {
+            store: Some(store),
+            ..cache
+        })
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryCache {
+    async fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.memory.get(key).map(|entry| entry.data)
+    }
+
+    async fn contains(&self, key: &str) -> bool {
+        self.memory.contains_key(key)
+    }
+
+    async fn insert(&self, key: String, data: Vec<u8>, ttl: Duration) {
+        let data = Arc::new(data);
+        if let Some(store) = &self.store {
+            let _ = store.send(Write::Insert {
+                key: key.clone(),
+                expires_at: get_timestamp_millis() + ttl.as_millis() as i64,
+                data: data.clone(),
+            });
+        }
+        self.memory.insert(key, CacheEntry { ttl, data }).await;
+    }
+
+    async fn remove(&self, key: &str) {
+        if let Some(store) = &self.store {
+            let _ = store.send(Write::Remove(key.to_string()));
+        }
+        self.memory.invalidate(key).await;
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let (res, answer) = oneshot::channel();
+        store
+            .send(Write::Ping(res))
+            .map_err(|_| "The persistent cache writer stopped".to_string())?;
+        answer
+            .await
+            .map_err(|_| "The persistent cache writer stopped".to_string())?
+    }
+}
+
+/// Cache shared by every replica through a Redis (or RESP-compatible) server. Nothing is kept
+/// locally, since other replicas can remove or replace entries at any time.
+pub struct RedisCache {
+    connection: ConnectionManager,
+}
+
+impl RedisCache {
+    pub async fn new(url: &str) -> Result<RedisCache, redis::RedisError> {
+        Ok(RedisCache {
+            connection: ConnectionManager::new(redis::Client::open(url)?).await?,
+        })
+    }
+}
+
+const REDIS_PREFIX: &str = "rs-pixel-backend:";
+
+#[async_trait]
+impl CacheBackend for RedisCache {
+    async fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        let mut connection = self.connection.clone();
+        match connection
+            .get::<_, Option<Vec<u8>>>(format!("{REDIS_PREFIX}{key}"))
+            .await
+        {
+            Ok(data) => data.map(Arc::new),
+            Err(err) => {
+                error!("Unable to read from the Redis cache: {err}");
+                None
+            }
+        }
+    }
+
+    async fn contains(&self, key: &str) -> bool {
+        let mut connection = self.connection.clone();
+        match connection
+            .exists::<_, bool>(format!("{REDIS_PREFIX}{key}"))
+            .await
+        {
+            Ok(exists) => exists,
+            Err(err) => {
+                error!("Unable to read from the Redis cache: {err}");
+                false
+            }
+        }
+    }
+
+    async fn insert(&self, key: String, data: Vec<u8>, ttl: Duration) {
+        let mut connection = self.connection.clone();
+        if let Err(err) = connection
+            .pset_ex::<_, _, ()>(
+                format!("{REDIS_PREFIX}{key}"),
+                data,
+                ttl.as_millis() as usize,
+            )
+            .await
+        {
+            error!("Unable to write to the Redis cache: {err}");
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        let mut connection = self.connection.clone();
+        if let Err(err) = connection
+            .del::<_, ()>(format!("{REDIS_PREFIX}{key}"))
+            .await
+        {
+            error!("Unable to delete from the Redis cache: {err}");
+        }
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        let mut connection = self.connection.clone();
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut connection)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Creates a cache key from a Hypixel API path and its parameters
+pub fn cache_key<'a>(path: &str, params: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let mut params = params.into_iter().collect::<Vec<_>>();
+    params.sort_unstable();
+
+    let mut key = path.to_string();
+    for (idx, (name, value)) in params.into_iter().enumerate() {
+        key.push(if idx == 0 { '?' } else { '&' });
+        key.push_str(name);
+        key.push('=');
+        key.push_str(value);
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        env,
+        io::{BufRead, BufReader, Write as _},
+        net::{TcpListener, TcpStream},
+        sync::Mutex,
+    };
+    use uuid::Uuid;
+
+    type Store = Arc<Mutex<HashMap<Vec<u8>, (Vec<u8>, Instant)>>>;
+
+    /// A RESP server implementing the commands used by `RedisCache`, returns its URL
+    fn fake_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let store = Store::default();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let store = store.clone();
+                thread::spawn(move || serve(stream.unwrap(), &store));
+            }
+        });
+        url
+    }
+
+    fn serve(stream: TcpStream, store: &Store) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        while let Some(command) = read_command(&mut reader) {
+            let mut store = store.lock().unwrap();
+            store.retain(|_, (_, expires_at)| *expires_at > Instant::now());
+            let reply = match (command[0].to_ascii_uppercase().as_slice(), &command[1..]) {
+                (b"PING", _) => b"+PONG\r\n".to_vec(),
+                (b"GET", [key]) => match store.get(key) {
+                    Some((data, _)) => {
+                        let mut reply = format!("${}\r\n", data.len()).into_bytes();
+                        reply.extend_from_slice(data);
+                        reply.extend_from_slice(b"\r\n");
+                        reply
+                    }
+                    None => b"$-1\r\n".to_vec(),
+                },
+                (b"EXISTS", [key]) => {
+                    format!(":{}\r\n", store.contains_key(key) as u8).into_bytes()
+                }
+                (b"PSETEX", [key, ttl, data]) => {
+                    let ttl = String::from_utf8_lossy(ttl).parse::<u64>().unwrap();
+                    let expires_at = Instant::now() + Duration::from_millis(ttl);
+                    store.insert(key.clone(), (data.clone(), expires_at));
+                    b"+OK\r\n".to_vec()
+                }
+                (b"DEL", [key]) => {
+                    format!(":{}\r\n", store.remove(key).is_some() as u8).into_bytes()
+                }
+                _ => b"-ERR unknown command\r\n".to_vec(),
+            };
+            writer.write_all(&reply).unwrap();
+        }
+    }
+
+    fn read_command(reader: &mut impl BufRead) -> Option<Vec<Vec<u8>>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok().filter(|&len| len > 0)?;
+        let count = line.trim_end().strip_prefix('*')?.parse::<usize>().ok()?;
+        (0..count)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).ok()?;
+                let len = line.trim_end().strip_prefix('$')?.parse::<usize>().ok()?;
+                let mut arg = vec![0; len + 2];
+                reader.read_exact(&mut arg).ok()?;
+                arg.truncate(len);
+                Some(arg)
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn replicas_see_responses_cached_by_each_other() {
+        let url = fake_redis();
+        let first = RedisCache::new(&url).await.unwrap();
+        let second = RedisCache::new(&url).await.unwrap();
+        assert!(first.ping().await.is_ok());
+
+        first
+            .insert(
+                "counts".to_string(),
+                b"{}".to_vec(),
+                Duration::from_secs(60),
+            )
+            .await;
+        assert!(first.contains("counts").await);
+        assert!(second.contains("counts").await);
+        assert_eq!(second.get("counts").await.unwrap().as_slice(), b"{}");
+        assert!(!second.contains("boosters").await);
+    }
+
+    #[actix_web::test]
+    async fn removals_reach_every_replica() {
+        let url = fake_redis();
+        let first = RedisCache::new(&url).await.unwrap();
+        let second = RedisCache::new(&url).await.unwrap();
+
+        first
+            .insert(
+                "counts".to_string(),
+                b"{}".to_vec(),
+                Duration::from_secs(60),
+            )
+            .await;
+        assert!(second.contains("counts").await);
+
+        first.remove("counts").await;
+        assert!(!second.contains("counts").await);
+        assert!(second.get("counts").await.is_none());
+        assert!(!first.contains("counts").await);
+    }
+
+    #[actix_web::test]
+    async fn entries_expire_with_their_ttl() {
+        let cache = RedisCache::new(&fake_redis()).await.unwrap();
+        cache
+            .insert(
+                "counts".to_string(),
+                b"{}".to_vec(),
+                Duration::from_millis(50),
+            )
+            .await;
+        assert!(cache.contains("counts").await);
+
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        assert!(!cache.contains("counts").await);
+        assert!(cache.get("counts").await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn persisted_entries_survive_a_restart_with_their_ttl() {
+        let dir = env::temp_dir().join(format!("rs-pixel-backend-{}", Uuid::new_v4()));
+        let cache = MemoryCache::persistent(&dir).await.unwrap();
+        cache
+            .insert(
+                "counts".to_string(),
+                b"{}".to_vec(),
+                Duration::from_secs(60),
+            )
+            .await;
+        cache
+            .insert(
+                "boosters".to_string(),
+                b"{}".to_vec(),
+                Duration::from_secs(1),
+            )
+            .await;
+        // Removals are written after the inserts they undo
+        for idx in 0..100 {
+            let key = format!("player?uuid={idx}");
+            cache
+                .insert(key.clone(), b"{".to_vec(), Duration::from_secs(60))
+                .await;
+            cache.remove(&key).await;
+        }
+        cache.ping().await.unwrap();
+        drop(cache);
+
+        let cache = MemoryCache::persistent(&dir).await.unwrap();
+        assert_eq!(cache.get("counts").await.unwrap().as_slice(), b"{}");
+        assert!(cache.contains("boosters").await);
+        assert!(!cache.contains("player?uuid=0").await);
+        assert!(!cache.contains("player?uuid=99").await);
+
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        assert!(!cache.contains("boosters").await);
+        assert!(cache.contains("counts").await);
+        drop(cache);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
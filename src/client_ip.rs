@@ -0,0 +1,248 @@
+use crate::config::{Config, ForwardedHeader};
+use actix_web::{
+    http::header::{HeaderName, FORWARDED, X_FORWARDED_FOR},
+    HttpRequest,
+};
+use ipnet::Ipv6Net;
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+const X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+
+/// Client that sent a request
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClientAddr {
+    Ip(IpAddr),
+    /// A hop that isn't an address (like `unknown` or an obfuscated identifier) and the trusted proxy that added it
+    Unresolved {
+        proxy: IpAddr,
+        node: String,
+    },
+}
+
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientAddr::Ip(ip) => write!(f, "{ip}"),
+            ClientAddr::Unresolved { proxy, node } => write!(f, "{proxy}/{node}"),
+        }
+    }
+}
+
+/// Client that sent a request, read from `SERVER.FORWARDED_HEADER` only when the connecting peer
+/// is one of the `TRUSTED_PROXIES`
+pub fn client_ip(req: &HttpRequest, config: &Config) -> Option<ClientAddr> {
+    let peer = req.peer_addr()?.ip().to_canonical();
+    if !is_trusted(config, peer) {
+        return Some(ClientAddr::Ip(peer));
+    }
+
+    // Each proxy appends the address it received the request from, so the client is the
+    // last hop that wasn't added by a trusted proxy
+    let mut proxy = peer;
+    for node in forwarded_for(req, config.server.forwarded_header)
+        .into_iter()
+        .rev()
+    {
+        let Some(hop) = parse_node(&node) else {
+            return Some(ClientAddr::Unresolved { proxy, node });
+        };
+        if !is_trusted(config, hop) {
+            return Some(ClientAddr::Ip(hop));
+        }
+        proxy = hop;
+    }
+    Some(ClientAddr::Ip(proxy))
+}
+
+/// Whether a request was sent from the server itself, without passing through a proxy
+pub fn is_local(req: &HttpRequest) -> bool {
+    req.peer_addr()
+        .is_some_and(|peer| peer.ip().to_canonical().is_loopback())
+        && [FORWARDED, X_FORWARDED_FOR, X_REAL_IP]
+            .iter()
+            .all(|name| !req.headers().contains_key(name))
+}
+
+/// Key of a client in the rate limiter, IPv6 clients usually get a whole network
+/// so they are grouped by `SERVER.IPV6_PREFIX`
+pub fn rate_limit_key(client: &ClientAddr, config: &Config) -> String {
+    match client {
+        ClientAddr::Ip(IpAddr::V6(ip)) => Ipv6Net::new(*ip, config.server.ipv6_prefix)
+            .map(|net| net.trunc().to_string())
+            .unwrap_or_else(|_| ip.to_string()),
+        client => client.to_string(),
+    }
+}
+
+fn is_trusted(config: &Config, ip: IpAddr) -> bool {
+    config
+        .trusted_proxies
+        .iter()
+        .any(|range| range.contains(&ip))
+}
+
+/// Nodes of the configured forwarding header, from the client to the last proxy
+fn forwarded_for(req: &HttpRequest, header: ForwardedHeader) -> Vec<String> {
+    let name = match header {
+        ForwardedHeader::Forwarded => FORWARDED,
+        ForwardedHeader::XForwardedFor => X_FORWARDED_FOR,
+        ForwardedHeader::XRealIp => X_REAL_IP,
+    };
+    let values = req
+        .headers()
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim);
+
+    match header {
+        ForwardedHeader::Forwarded => values
+            .map(|element| {
+                element
+                    .split(';')
+                    .find_map(|pair| {
+                        let (name, value) = pair.split_once('=')?;
+                        name.trim()
+                            .eq_ignore_ascii_case("for")
+                            .then(|| value.trim().trim_matches('"').to_string())
+                    })
+                    .unwrap_or_default()
+            })
+            .collect(),
+        _ => values.map(str::to_string).collect(),
+    }
+}
+
+/// Parses an address which may have a port, like `1.2.3.4:80` or `[2001:db8::1]:80`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let ip = match node.strip_prefix('[') {
+        Some(node) => node.split(']').next()?.parse::<IpAddr>().ok()?,
+        None => node
+            .parse::<IpAddr>()
+            .ok()
+            .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))?,
+    };
+    Some(ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::config;
+    use actix_web::test::TestRequest;
+
+    const X_FORWARDED_FOR: &str = "TRUSTED_PROXIES = \"10.0.0.0/8\"";
+    const FORWARDED: &str =
+        "TRUSTED_PROXIES = \"10.0.0.0/8\"\n[SERVER]\nFORWARDED_HEADER = \"Forwarded\"";
+
+    fn client(settings: &str, peer: &str, headers: &[(&str, &str)]) -> ClientAddr {
+        let mut req = TestRequest::default().peer_addr(peer.parse().unwrap());
+        for &header in headers {
+            req = req.insert_header(header);
+        }
+        client_ip(&req.to_http_request(), &config(settings)).unwrap()
+    }
+
+    fn ip(ip: &str) -> ClientAddr {
+        ClientAddr::Ip(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn headers_of_untrusted_peers_are_ignored() {
+        let client = client(
+            X_FORWARDED_FOR,
+            "1.2.3.4:5000",
+            &[("X-Forwarded-For", "5.6.7.8")],
+        );
+        assert_eq!(client, ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn only_the_configured_header_is_read() {
+        let headers = [
+            ("Forwarded", "for=9.9.9.9"),
+            ("X-Forwarded-For", "5.6.7.8"),
+            ("X-Real-IP", "8.8.8.8"),
+        ];
+        assert_eq!(
+            client(X_FORWARDED_FOR, "10.0.0.1:5000", &headers),
+            ip("5.6.7.8")
+        );
+        assert_eq!(client(FORWARDED, "10.0.0.1:5000", &headers), ip("9.9.9.9"));
+        assert_eq!(
+            client(
+                X_FORWARDED_FOR,
+                "10.0.0.1:5000",
+                &[("Forwarded", "for=9.9.9.9")]
+            ),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn spoofed_hops_before_the_client_are_ignored() {
+        let client = client(
+            X_FORWARDED_FOR,
+            "10.0.0.1:5000",
+            &[("X-Forwarded-For", "6.6.6.6, 5.6.7.8, 10.0.0.2")],
+        );
+        assert_eq!(client, ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn garbage_hops_are_keyed_by_the_proxy_that_added_them() {
+        let config = config(X_FORWARDED_FOR);
+        let garbage = client(
+            X_FORWARDED_FOR,
+            "10.0.0.1:5000",
+            &[("X-Forwarded-For", "5.6.7.8, garbage, 10.0.0.2")],
+        );
+        assert_eq!(
+            garbage,
+            ClientAddr::Unresolved {
+                proxy: "10.0.0.2".parse().unwrap(),
+                node: "garbage".to_string(),
+            }
+        );
+        assert_eq!(rate_limit_key(&garbage, &config), "10.0.0.2/garbage");
+
+        let other = client(
+            X_FORWARDED_FOR,
+            "10.0.0.1:5000",
+            &[("X-Forwarded-For", "other")],
+        );
+        assert_eq!(rate_limit_key(&other, &config), "10.0.0.1/other");
+    }
+
+    #[test]
+    fn forwarded_elements_are_parsed() {
+        let v6 = client(
+            FORWARDED,
+            "10.0.0.1:5000",
+            &[(
+                "Forwarded",
+                "for=1.2.3.4, for=\"[2001:db8::1]:80\";proto=https",
+            )],
+        );
+        assert_eq!(v6, ip("2001:db8::1"));
+        assert_eq!(rate_limit_key(&v6, &config(FORWARDED)), "2001:db8::/64");
+
+        let unknown = client(FORWARDED, "10.0.0.1:5000", &[("Forwarded", "for=unknown")]);
+        assert_eq!(unknown.to_string(), "10.0.0.1/unknown");
+        let missing = client(FORWARDED, "10.0.0.1:5000", &[("Forwarded", "proto=https")]);
+        assert_eq!(missing.to_string(), "10.0.0.1/");
+    }
+
+    #[test]
+    fn trusted_hops_fall_back_to_the_first_proxy() {
+        let client = client(
+            X_FORWARDED_FOR,
+            "10.0.0.1:5000",
+            &[("X-Forwarded-For", "10.0.0.3, 10.0.0.2")],
+        );
+        assert_eq!(client, ip("10.0.0.3"));
+    }
+}
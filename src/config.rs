@@ -0,0 +1,1039 @@
+use crate::utils::{HYPIXEL_ENDPOINTS, SERVER_ENDPOINTS};
+use ipnet::IpNet;
+use rs_pixel::util::minecraft::ApiType;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    ffi::OsString,
+    fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+use tracing_subscriber::EnvFilter;
+
+/// Names of the settings that aren't per endpoint
+const SETTINGS: [&str; 34] = [
+    "BASE_URL",
+    "PORT",
+    "API_KEYS",
+    "API_KEY",
+    "API_POOL_SIZE",
+    "MINECRAFT_API_TYPE",
+    "MINECRAFT_BREAKER_THRESHOLD",
+    "MINECRAFT_BREAKER_COOLDOWN",
+    "RATE_LIMIT_STRATEGY",
+    "MINECRAFT_CACHE_TTL",
+    "CACHE_BACKEND",
+    "CACHE_DIR",
+    "REDIS_URL",
+    "READY_CHECK_INTERVAL",
+    "LOG_LEVEL",
+    "LOG_FORMAT",
+    "SERVER.PERIOD",
+    "SERVER.BURST",
+    "SERVER.IPV6_PREFIX",
+    "SERVER.FORWARDED_HEADER",
+    "TRUSTED_PROXIES",
+    "BATCH_CONCURRENCY",
+    "BATCH_MAX_SIZE",
+    "EXPAND_CONCURRENCY",
+    "AUCTION_SNAPSHOT_INTERVAL",
+    "SALES_DB",
+    "SALES_POLL_INTERVAL",
+    "BAZAAR_DB",
+    "BAZAAR_SAMPLE_INTERVAL",
+    "BAZAAR_RETENTION.MINUTE",
+    "BAZAAR_RETENTION.HOUR",
+    "BAZAAR_RETENTION.DAY",
+    "CLIENT_KEYS_FILE",
+    "CLIENT_KEYS_REQUIRED",
+];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MinecraftApiType {
+    Mojang,
+    Ashcon,
+    PlayerDb,
+}
+
+impl MinecraftApiType {
+    pub const ALL: [MinecraftApiType; 3] = [
+        MinecraftApiType::Mojang,
+        MinecraftApiType::Ashcon,
+        MinecraftApiType::PlayerDb,
+    ];
+
+    pub fn api_type(self) -> ApiType {
+        match self {
+            MinecraftApiType::Mojang => ApiType::Mojang,
+            MinecraftApiType::Ashcon => ApiType::Ashcon,
+            MinecraftApiType::PlayerDb => ApiType::PlayerDb,
+        }
+    }
+}
+
+impl FromStr for MinecraftApiType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Mojang" => Ok(MinecraftApiType::Mojang),
+            "Ashcon" => Ok(MinecraftApiType::Ashcon),
+            "PlayerDb" => Ok(MinecraftApiType::PlayerDb),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimit {
+    Delay,
+    Error,
+}
+
+impl FromStr for RateLimit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Delay" => Ok(RateLimit::Delay),
+            "Error" => Ok(RateLimit::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Header that trusted proxies set the client address with
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    Forwarded,
+    XForwardedFor,
+    XRealIp,
+}
+
+impl FromStr for ForwardedHeader {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "forwarded" => Ok(ForwardedHeader::Forwarded),
+            "x-forwarded-for" => Ok(ForwardedHeader::XForwardedFor),
+            "x-real-ip" => Ok(ForwardedHeader::XRealIp),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheBackendType {
+    Memory,
+    Redis,
+}
+
+impl FromStr for CacheBackendType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Memory" => Ok(CacheBackendType::Memory),
+            "Redis" => Ok(CacheBackendType::Redis),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Pretty" => Ok(LogFormat::Pretty),
+            "Json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Time (milliseconds) after which the rate limit quota is replenished
+    pub period: Option<u64>,
+    pub burst: Option<u32>,
+    /// IPv6 clients in the same network of this prefix length share a quota
+    pub ipv6_prefix: u8,
+    /// The only header read from `TRUSTED_PROXIES`
+    pub forwarded_header: ForwardedHeader,
+    pub endpoint: BTreeMap<&'static str, bool>,
+}
+
+/// API key presented by clients of the server with `X-Api-Key` or `?key=`
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ClientKey {
+    #[serde(serialize_with = "serialize_key")]
+    pub key: String,
+    /// Shown in the access log instead of the key
+    pub name: Option<String>,
+    /// Time (milliseconds) after which the quota is replenished, defaults to `SERVER.PERIOD`
+    pub period: Option<u64>,
+    /// Defaults to `SERVER.BURST`
+    pub burst: Option<u32>,
+    /// `SERVER_ENDPOINTS` names the key can use, every enabled endpoint if unset
+    pub endpoints: Option<BTreeSet<String>>,
+    /// Whether the key can use admin endpoints
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl ClientKey {
+    /// Name of the client in logs
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| mask(&self.key))
+    }
+}
+
+/// Effective configuration, read from an optional TOML or YAML file and overridden by environment variables
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub base_url: String,
+    pub port: u16,
+    #[serde(serialize_with = "serialize_keys")]
+    pub api_keys: Vec<String>,
+    pub api_pool_size: usize,
+    /// Tried in order until one of them responds
+    pub minecraft_api_type: Vec<MinecraftApiType>,
+    /// Consecutive failures after which a Minecraft API is skipped
+    pub minecraft_breaker_threshold: u32,
+    /// Seconds a failing Minecraft API is skipped for
+    pub minecraft_breaker_cooldown: u64,
+    pub rate_limit_strategy: RateLimit,
+    /// Seconds
+    pub minecraft_cache_ttl: Option<u64>,
+    pub cache_backend: CacheBackendType,
+    pub cache_dir: Option<PathBuf>,
+    #[serde(serialize_with = "serialize_url")]
+    pub redis_url: Option<String>,
+    /// Seconds
+    pub ready_check_interval: u64,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    /// Peers allowed to set the client IP with `Forwarded`, `X-Forwarded-For`, or `X-Real-IP`
+    pub trusted_proxies: Vec<IpNet>,
+    /// Operations of a `/batch` request that run at the same time
+    pub batch_concurrency: usize,
+    pub batch_max_size: usize,
+    /// Guild members expanded at the same time
+    pub expand_concurrency: usize,
+    /// Seconds between checks for an auction house update in the background
+    pub auction_snapshot_interval: Option<u64>,
+    /// SQLite database ended auctions are recorded in
+    pub sales_db: Option<PathBuf>,
+    /// Seconds between requests for ended auctions
+    pub sales_poll_interval: u64,
+    /// SQLite database bazaar prices are recorded in
+    pub bazaar_db: Option<PathBuf>,
+    /// Seconds between bazaar samples
+    pub bazaar_sample_interval: u64,
+    /// Days minute samples are kept for
+    pub bazaar_retention_minute: u64,
+    /// Days hourly averages are kept for
+    pub bazaar_retention_hour: u64,
+    /// Days daily averages are kept for, forever if unset
+    pub bazaar_retention_day: Option<u64>,
+    /// Seconds, by `HYPIXEL_ENDPOINTS` name
+    pub hypixel_cache_ttl: BTreeMap<&'static str, u64>,
+    pub server: ServerConfig,
+    pub client_keys: Vec<ClientKey>,
+    pub client_keys_file: Option<PathBuf>,
+    /// Whether requests without a client key are rejected
+    pub client_keys_required: bool,
+}
+
+/// The first characters of a key, to show it without leaking it
+pub fn mask(key: &str) -> String {
+    format!("{}...", key.chars().take(8).collect::<String>())
+}
+
+fn serialize_keys<S: Serializer>(keys: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(keys.iter().map(|key| mask(key)))
+}
+
+fn serialize_key<S: Serializer>(key: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&mask(key))
+}
+
+/// Hides the credentials of a URL
+fn serialize_url<S: Serializer>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    let url = url
+        .as_ref()
+        .map(|url| match (url.find("://"), url.rfind('@')) {
+            (Some(scheme), Some(at)) if scheme < at => {
+                format!("{}***{}", &url[..scheme + 3], &url[at..])
+            }
+            _ => url.clone(),
+        });
+    url.serialize(serializer)
+}
+
+/// Every problem found while loading the configuration
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Raw setting values before they are parsed
+struct Source {
+    file: BTreeMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl Source {
+    fn get(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or_else(|| self.file.get(name).cloned())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let value = self.get(name)?;
+        match value.parse::<T>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.errors
+                    .push(format!("{name}: expected {expected}, found `{value}`"));
+                None
+            }
+        }
+    }
+
+    /// Like `parse` but also rejects zero
+    fn parse_positive<T: FromStr + Default + PartialEq>(
+        &mut self,
+        name: &str,
+        expected: &str,
+    ) -> Option<T> {
+        let value = self.parse::<T>(name, expected)?;
+        if value == T::default() {
+            self.errors.push(format!("{name}: must be greater than 0"));
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn required<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        if self.get(name).is_none() {
+            self.errors.push(format!("{name}: missing"));
+            None
+        } else {
+            self.parse(name, expected)
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration, reporting every invalid setting at once
+    pub fn load(file: Option<&Path>) -> Result<Config, ConfigError> {
+        let mut source = Source {
+            file: BTreeMap::new(),
+            errors: Vec::new(),
+        };
+        // Client keys are a list of tables, so they are kept out of the flattened settings
+        let mut client_keys = None;
+        if let Some(file) = file {
+            match read_file(file) {
+                Ok(mut value) => {
+                    client_keys = take_client_keys(&mut value);
+                    flatten(None, &value, &mut source.file);
+                }
+                Err(err) => source.errors.push(format!("{}: {err}", file.display())),
+            }
+        }
+
+        for name in source.file.keys() {
+            if !is_setting(name) {
+                source.errors.push(format!("{name}: unknown setting"));
+            }
+        }
+        source
+            .errors
+            .extend(unknown_env_endpoints(env::vars_os().map(|(name, _)| name)));
+
+        let base_url = source.required::<String>("BASE_URL", "an address");
+        let port = source.required::<u16>("PORT", "a port number");
+        let api_keys = source
+            .get("API_KEYS")
+            .or_else(|| source.get("API_KEY"))
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if api_keys.is_empty() {
+            source.errors.push("API_KEYS: missing".to_string());
+        }
+        let api_pool_size = source
+            .parse_positive::<usize>("API_POOL_SIZE", "a number")
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |v| v.get()));
+        let mut minecraft_api_type = Vec::new();
+        for api in source
+            .get("MINECRAFT_API_TYPE")
+            .unwrap_or_else(|| "Mojang".to_string())
+            .split(',')
+            .map(str::trim)
+        {
+            match api.parse::<MinecraftApiType>() {
+                Ok(api) if minecraft_api_type.contains(&api) => source
+                    .errors
+                    .push(format!("MINECRAFT_API_TYPE: {api:?} is listed more than once")),
+                Ok(api) => minecraft_api_type.push(api),
+                Err(()) => source.errors.push(format!(
+                    "MINECRAFT_API_TYPE: expected a list of Mojang, Ashcon, or PlayerDb, found `{api}`"
+                )),
+            }
+        }
+        let minecraft_breaker_threshold = source
+            .parse_positive("MINECRAFT_BREAKER_THRESHOLD", "a number")
+            .unwrap_or(5);
+        let minecraft_breaker_cooldown = source
+            .parse_positive("MINECRAFT_BREAKER_COOLDOWN", "a number of seconds")
+            .unwrap_or(30);
+        let rate_limit_strategy = source
+            .parse("RATE_LIMIT_STRATEGY", "Delay or Error")
+            .unwrap_or(RateLimit::Delay);
+        let minecraft_cache_ttl = source.parse::<u64>("MINECRAFT_CACHE_TTL", "a number of seconds");
+        let cache_backend = source
+            .parse("CACHE_BACKEND", "Memory or Redis")
+            .unwrap_or(CacheBackendType::Memory);
+        let cache_dir = source.get("CACHE_DIR").map(PathBuf::from);
+        let redis_url = source.get("REDIS_URL");
+        if cache_backend == CacheBackendType::Redis && redis_url.is_none() {
+            source
+                .errors
+                .push("REDIS_URL: required by the Redis cache backend".to_string());
+        }
+        let ready_check_interval = source
+            .parse::<u64>("READY_CHECK_INTERVAL", "a number of seconds")
+            .unwrap_or(30);
+        let log_level = source
+            .get("LOG_LEVEL")
+            .unwrap_or_else(|| "info".to_string());
+        if EnvFilter::try_new(&log_level).is_err() {
+            source.errors.push(format!(
+                "LOG_LEVEL: expected a level or filter directives, found `{log_level}`"
+            ));
+        }
+        let log_format = source
+            .parse("LOG_FORMAT", "Pretty or Json")
+            .unwrap_or(LogFormat::Pretty);
+
+        let trusted_proxies = source
+            .get("TRUSTED_PROXIES")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|range| !range.is_empty())
+                    .filter_map(|range| match parse_ip_range(range) {
+                        Some(range) => Some(range),
+                        None => {
+                            source.errors.push(format!(
+                                "TRUSTED_PROXIES: expected IP addresses or CIDR ranges, found `{range}`"
+                            ));
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let batch_concurrency = source
+            .parse_positive("BATCH_CONCURRENCY", "a number")
+            .unwrap_or(8);
+        let batch_max_size = source
+            .parse_positive("BATCH_MAX_SIZE", "a number")
+            .unwrap_or(200);
+        let expand_concurrency = source
+            .parse_positive("EXPAND_CONCURRENCY", "a number")
+            .unwrap_or(8);
+        let auction_snapshot_interval =
+            source.parse_positive::<u64>("AUCTION_SNAPSHOT_INTERVAL", "a number of seconds");
+        let sales_db = source.get("SALES_DB").map(PathBuf::from);
+        let sales_poll_interval = source
+            .parse_positive("SALES_POLL_INTERVAL", "a number of seconds")
+            .unwrap_or(30);
+        let bazaar_db = source.get("BAZAAR_DB").map(PathBuf::from);
+        let bazaar_sample_interval = source
+            .parse_positive("BAZAAR_SAMPLE_INTERVAL", "a number of seconds")
+            .unwrap_or(60);
+        let bazaar_retention_minute = source
+            .parse_positive("BAZAAR_RETENTION.MINUTE", "a number of days")
+            .unwrap_or(1);
+        let bazaar_retention_hour = source
+            .parse_positive("BAZAAR_RETENTION.HOUR", "a number of days")
+            .unwrap_or(30);
+        let bazaar_retention_day =
+            source.parse_positive::<u64>("BAZAAR_RETENTION.DAY", "a number of days");
+
+        let mut hypixel_cache_ttl = BTreeMap::new();
+        for (name, _, _) in HYPIXEL_ENDPOINTS {
+            if let Some(ttl) =
+                source.parse::<u64>(&format!("HYPIXEL_CACHE_TTL.{name}"), "a number of seconds")
+            {
+                hypixel_cache_ttl.insert(name, ttl);
+            }
+        }
+
+        let mut endpoint = BTreeMap::new();
+        for (name, _) in SERVER_ENDPOINTS {
+            if let Some(enable) =
+                source.parse::<bool>(&format!("SERVER.ENDPOINT.{name}"), "true or false")
+            {
+                endpoint.insert(name, enable);
+            }
+        }
+        let server = ServerConfig {
+            period: source.parse_positive("SERVER.PERIOD", "a number of milliseconds"),
+            burst: source.parse_positive("SERVER.BURST", "a number"),
+            ipv6_prefix: match source.parse_positive::<u8>("SERVER.IPV6_PREFIX", "a prefix length")
+            {
+                Some(prefix) if prefix > 128 => {
+                    source
+                        .errors
+                        .push("SERVER.IPV6_PREFIX: must be at most 128".to_string());
+                    64
+                }
+                prefix => prefix.unwrap_or(64),
+            },
+            forwarded_header: source
+                .parse(
+                    "SERVER.FORWARDED_HEADER",
+                    "Forwarded, X-Forwarded-For, or X-Real-IP",
+                )
+                .unwrap_or(ForwardedHeader::XForwardedFor),
+            endpoint,
+        };
+
+        let client_keys_file = source.get("CLIENT_KEYS_FILE").map(PathBuf::from);
+        if let Some(file) = &client_keys_file {
+            match read_file(file) {
+                Ok(mut value) => {
+                    client_keys = take_client_keys(&mut value);
+                    if client_keys.is_none() {
+                        source.errors.push(format!(
+                            "CLIENT_KEYS_FILE: {} has no CLIENT_KEYS list",
+                            file.display()
+                        ));
+                    }
+                }
+                Err(err) => source
+                    .errors
+                    .push(format!("CLIENT_KEYS_FILE: {}: {err}", file.display())),
+            }
+        }
+        let client_keys = client_keys
+            .map(|value| parse_client_keys(value, &mut source.errors))
+            .unwrap_or_default();
+        let client_keys_required = source
+            .parse("CLIENT_KEYS_REQUIRED", "true or false")
+            .unwrap_or(false);
+        if client_keys_required && client_keys.is_empty() {
+            source
+                .errors
+                .push("CLIENT_KEYS_REQUIRED: no client keys are configured".to_string());
+        }
+
+        if !source.errors.is_empty() {
+            return Err(ConfigError(source.errors));
+        }
+
+        Ok(Config {
+            base_url: base_url.unwrap(),
+            port: port.unwrap(),
+            api_keys,
+            api_pool_size,
+            minecraft_api_type,
+            minecraft_breaker_threshold,
+            minecraft_breaker_cooldown,
+            rate_limit_strategy,
+            minecraft_cache_ttl,
+            cache_backend,
+            cache_dir,
+            redis_url,
+            ready_check_interval,
+            log_level,
+            log_format,
+            trusted_proxies,
+            batch_concurrency,
+            batch_max_size,
+            expand_concurrency,
+            auction_snapshot_interval,
+            sales_db,
+            sales_poll_interval,
+            bazaar_db,
+            bazaar_sample_interval,
+            bazaar_retention_minute,
+            bazaar_retention_hour,
+            bazaar_retention_day,
+            hypixel_cache_ttl,
+            server,
+            client_keys,
+            client_keys_file,
+            client_keys_required,
+        })
+    }
+}
+
+impl Config {
+    /// Cache TTLs keyed by Hypixel API path
+    pub fn cache_ttls(&self) -> HashMap<String, Duration> {
+        HYPIXEL_ENDPOINTS
+            .iter()
+            .filter_map(|(name, endpoint, _)| {
+                self.hypixel_cache_ttl
+                    .get(name)
+                    .map(|ttl| (endpoint.get_path(), Duration::from_secs(*ttl)))
+            })
+            .collect()
+    }
+
+    /// The configured client key matching `key`
+    pub fn client_key(&self, key: &str) -> Option<&ClientKey> {
+        self.client_keys.iter().find(|client| client.key == key)
+    }
+
+    /// This config with the settings that can be reloaded taken from `new`
+    pub fn with_reloadable(&self, new: &Config) -> Config {
+        Config {
+            trusted_proxies: new.trusted_proxies.clone(),
+            batch_concurrency: new.batch_concurrency,
+            batch_max_size: new.batch_max_size,
+            expand_concurrency: new.expand_concurrency,
+            hypixel_cache_ttl: new.hypixel_cache_ttl.clone(),
+            server: new.server.clone(),
+            client_keys: new.client_keys.clone(),
+            client_keys_file: new.client_keys_file.clone(),
+            client_keys_required: new.client_keys_required,
+            ..self.clone()
+        }
+    }
+
+    /// Names of the settings that differ from `current` but are only applied after a restart
+    pub fn restart_required(&self, current: &Config) -> Vec<&'static str> {
+        // Destructured so that new settings have to be listed here or in `with_reloadable`
+        let Config {
+            base_url,
+            port,
+            api_keys,
+            api_pool_size,
+            minecraft_api_type,
+            minecraft_breaker_threshold,
+            minecraft_breaker_cooldown,
+            rate_limit_strategy,
+            minecraft_cache_ttl,
+            cache_backend,
+            cache_dir,
+            redis_url,
+            ready_check_interval,
+            log_level,
+            log_format,
+            auction_snapshot_interval,
+            sales_db,
+            sales_poll_interval,
+            bazaar_db,
+            bazaar_sample_interval,
+            bazaar_retention_minute,
+            bazaar_retention_hour,
+            bazaar_retention_day,
+            trusted_proxies: _,
+            batch_concurrency: _,
+            batch_max_size: _,
+            expand_concurrency: _,
+            hypixel_cache_ttl: _,
+            server: _,
+            client_keys: _,
+            client_keys_file: _,
+            client_keys_required: _,
+        } = self;
+
+        [
+            ("BASE_URL", *base_url != current.base_url),
+            ("PORT", *port != current.port),
+            ("API_KEYS", *api_keys != current.api_keys),
+            ("API_POOL_SIZE", *api_pool_size != current.api_pool_size),
+            (
+                "MINECRAFT_API_TYPE",
+                *minecraft_api_type != current.minecraft_api_type,
+            ),
+            (
+                "MINECRAFT_BREAKER_THRESHOLD",
+                *minecraft_breaker_threshold != current.minecraft_breaker_threshold,
+            ),
+            (
+                "MINECRAFT_BREAKER_COOLDOWN",
+                *minecraft_breaker_cooldown != current.minecraft_breaker_cooldown,
+            ),
+            (
+                "RATE_LIMIT_STRATEGY",
+                *rate_limit_strategy != current.rate_limit_strategy,
+            ),
+            (
+                "MINECRAFT_CACHE_TTL",
+                *minecraft_cache_ttl != current.minecraft_cache_ttl,
+            ),
+            ("CACHE_BACKEND", *cache_backend != current.cache_backend),
+            ("CACHE_DIR", *cache_dir != current.cache_dir),
+            ("REDIS_URL", *redis_url != current.redis_url),
+            (
+                "READY_CHECK_INTERVAL",
+                *ready_check_interval != current.ready_check_interval,
+            ),
+            ("LOG_LEVEL", *log_level != current.log_level),
+            ("LOG_FORMAT", *log_format != current.log_format),
+            (
+                "AUCTION_SNAPSHOT_INTERVAL",
+                *auction_snapshot_interval != current.auction_snapshot_interval,
+            ),
+            ("SALES_DB", *sales_db != current.sales_db),
+            (
+                "SALES_POLL_INTERVAL",
+                *sales_poll_interval != current.sales_poll_interval,
+            ),
+            ("BAZAAR_DB", *bazaar_db != current.bazaar_db),
+            (
+                "BAZAAR_SAMPLE_INTERVAL",
+                *bazaar_sample_interval != current.bazaar_sample_interval,
+            ),
+            (
+                "BAZAAR_RETENTION.MINUTE",
+                *bazaar_retention_minute != current.bazaar_retention_minute,
+            ),
+            (
+                "BAZAAR_RETENTION.HOUR",
+                *bazaar_retention_hour != current.bazaar_retention_hour,
+            ),
+            (
+                "BAZAAR_RETENTION.DAY",
+                *bazaar_retention_day != current.bazaar_retention_day,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// Settings of a config file that environment variables override, so changing them in the file has no effect
+pub fn shadowed_by_env(file: &Path) -> Vec<String> {
+    let mut values = BTreeMap::new();
+    if let Ok(mut value) = read_file(file) {
+        take_client_keys(&mut value);
+        flatten(None, &value, &mut values);
+    }
+    values
+        .into_keys()
+        .filter(|name| env::var(name).is_ok_and(|value| !value.trim().is_empty()))
+        .collect()
+}
+
+/// A CIDR range or a single address
+fn parse_ip_range(range: &str) -> Option<IpNet> {
+    range
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| range.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+fn is_setting(name: &str) -> bool {
+    SETTINGS.contains(&name)
+        || name
+            .strip_prefix("HYPIXEL_CACHE_TTL.")
+            .is_some_and(|endpoint| {
+                HYPIXEL_ENDPOINTS
+                    .iter()
+                    .any(|(name, _, _)| *name == endpoint)
+            })
+        || name
+            .strip_prefix("SERVER.ENDPOINT.")
+            .is_some_and(|endpoint| SERVER_ENDPOINTS.iter().any(|(name, _)| *name == endpoint))
+}
+
+/// Per endpoint environment variables naming an unknown endpoint, variables whose name isn't
+/// UTF-8 can't be settings so they are skipped
+fn unknown_env_endpoints(names: impl Iterator<Item = OsString>) -> Vec<String> {
+    names
+        .filter_map(|name| name.into_string().ok())
+        .filter(|name| {
+            (name.starts_with("HYPIXEL_CACHE_TTL.") || name.starts_with("SERVER.ENDPOINT."))
+                && !is_setting(name)
+        })
+        .map(|name| format!("{name}: unknown endpoint"))
+        .collect()
+}
+
+/// Parses a TOML or YAML file
+fn read_file(file: &Path) -> Result<Value, String> {
+    let content = fs::read_to_string(file).map_err(|err| err.to_string())?;
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&content).map_err(|err| err.to_string()),
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str::<Value>(&content).map_err(|err| err.to_string())
+        }
+        _ => Err("expected a .toml, .yaml, or .yml file".to_string()),
+    }
+}
+
+/// Removes the `CLIENT_KEYS` list from a parsed file
+fn take_client_keys(value: &mut Value) -> Option<Value> {
+    let map = value.as_object_mut()?;
+    let name = map
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case("CLIENT_KEYS"))?
+        .clone();
+    map.remove(&name)
+}
+
+fn parse_client_keys(value: Value, errors: &mut Vec<String>) -> Vec<ClientKey> {
+    let Value::Array(items) = value else {
+        errors.push("CLIENT_KEYS: expected a list of keys".to_string());
+        return Vec::new();
+    };
+
+    let mut client_keys: Vec<ClientKey> = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        // Fields are case-insensitive like the other settings
+        let item = match item {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key.to_lowercase(), value))
+                    .collect::<Map<_, _>>(),
+            ),
+            item => item,
+        };
+        let client = match serde_json::from_value::<ClientKey>(item) {
+            Ok(client) => client,
+            Err(err) => {
+                errors.push(format!("CLIENT_KEYS[{i}]: {err}"));
+                continue;
+            }
+        };
+
+        if client.key.trim().is_empty() {
+            errors.push(format!("CLIENT_KEYS[{i}].KEY: must not be empty"));
+        } else if client_keys.iter().any(|other| other.key == client.key) {
+            errors.push(format!("CLIENT_KEYS[{i}].KEY: duplicate key"));
+        }
+        if client.period == Some(0) {
+            errors.push(format!("CLIENT_KEYS[{i}].PERIOD: must be greater than 0"));
+        }
+        if client.burst == Some(0) {
+            errors.push(format!("CLIENT_KEYS[{i}].BURST: must be greater than 0"));
+        }
+        for endpoint in client.endpoints.iter().flatten() {
+            if !SERVER_ENDPOINTS.iter().any(|(name, _)| name == endpoint) {
+                errors.push(format!(
+                    "CLIENT_KEYS[{i}].ENDPOINTS: unknown endpoint `{endpoint}`"
+                ));
+            }
+        }
+        client_keys.push(client);
+    }
+    client_keys
+}
+
+/// Reads parsed settings into values named like their environment variables,
+/// so `[SERVER.ENDPOINT] PLAYER = true` becomes `SERVER.ENDPOINT.PLAYER`
+fn flatten(name: Option<&str>, value: &Value, values: &mut BTreeMap<String, String>) {
+    let name_str = name.unwrap_or_default().to_string();
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = key.to_uppercase();
+                let child = match name {
+                    Some(name) => format!("{name}.{key}"),
+                    None => key,
+                };
+                flatten(Some(&child), value, values);
+            }
+        }
+        Value::Array(array) => {
+            let items = array
+                .iter()
+                .map(|item| match item {
+                    Value::String(item) => item.clone(),
+                    item => item.to_string(),
+                })
+                .collect::<Vec<_>>();
+            values.insert(name_str, items.join(","));
+        }
+        Value::String(value) => {
+            values.insert(name_str, value.clone());
+        }
+        Value::Null => {}
+        value => {
+            values.insert(name_str, value.to_string());
+        }
+    }
+}
+
+/// Command line arguments
+pub struct Args {
+    /// Configuration file, falls back to the `CONFIG_FILE` environment variable
+    pub config: Option<PathBuf>,
+    /// Print the effective configuration and exit
+    pub check_config: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Args, String> {
+        let mut args = Args {
+            config: None,
+            check_config: false,
+        };
+
+        let mut iter = env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--check-config" => args.check_config = true,
+                "--config" => {
+                    args.config = Some(
+                        iter.next()
+                            .map(PathBuf::from)
+                            .ok_or("Missing value for --config")?,
+                    )
+                }
+                _ => match arg.strip_prefix("--config=") {
+                    Some(file) => args.config = Some(PathBuf::from(file)),
+                    None => return Err(format!("Unknown argument {arg}")),
+                },
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStringExt;
+    use uuid::Uuid;
+
+    const REQUIRED: &str = "BASE_URL = \"127.0.0.1\"\nPORT = 8000\nAPI_KEYS = \"test-key\"\n";
+
+    fn load(toml: &str) -> Result<Config, ConfigError> {
+        let path = env::temp_dir().join(format!("rs-pixel-backend-{}.toml", Uuid::new_v4()));
+        fs::write(&path, toml).unwrap();
+        let config = Config::load(Some(&path));
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn environment_variables_override_the_file() {
+        // Only read at startup, so other tests don't depend on it
+        env::set_var("BAZAAR_SAMPLE_INTERVAL", "15");
+        let config = load(&format!("{REQUIRED}BAZAAR_SAMPLE_INTERVAL = 60"));
+        env::remove_var("BAZAAR_SAMPLE_INTERVAL");
+
+        assert_eq!(config.unwrap().bazaar_sample_interval, 15);
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported_at_once() {
+        let err = load(
+            "BASE_URL = \"127.0.0.1\"\n\
+            PORT = 70000\n\
+            API_POOL_SIZE = 0\n\
+            MINECRAFT_API_TYPE = \"Mojang,Mojang\"\n\
+            RATE_LIMIT_STRATEGY = \"Sometimes\"",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.0,
+            [
+                "PORT: expected a port number, found `70000`",
+                "API_KEYS: missing",
+                "API_POOL_SIZE: must be greater than 0",
+                "MINECRAFT_API_TYPE: Mojang is listed more than once",
+                "RATE_LIMIT_STRATEGY: expected Delay or Error, found `Sometimes`",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_settings_and_wrong_types_are_reported() {
+        let err = load(&format!(
+            "{REQUIRED}\
+            NOT_A_SETTING = 1\n\
+            BATCH_MAX_SIZE = \"many\"\n\
+            [HYPIXEL_CACHE_TTL]\nNOT_AN_ENDPOINT = 60\n\
+            [SERVER.ENDPOINT]\nPLAYER = \"yes\"\n\
+            [[CLIENT_KEYS]]\nKEY = \"client\"\nBURST = \"ten\""
+        ))
+        .unwrap_err();
+
+        for error in [
+            "HYPIXEL_CACHE_TTL.NOT_AN_ENDPOINT: unknown setting",
+            "NOT_A_SETTING: unknown setting",
+            "BATCH_MAX_SIZE: expected a number, found `many`",
+            "SERVER.ENDPOINT.PLAYER: expected true or false, found `yes`",
+        ] {
+            assert!(err.0.iter().any(|e| e == error), "{error} in {err}");
+        }
+        assert!(
+            err.0.iter().any(|e| e.starts_with("CLIENT_KEYS[0]: ")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn unknown_endpoints_in_the_environment_are_reported() {
+        let names = [
+            OsString::from("SERVER.ENDPOINT.PLAYER"),
+            OsString::from("SERVER.ENDPOINT.NOT_AN_ENDPOINT"),
+            OsString::from("HYPIXEL_CACHE_TTL.COUNTS"),
+            OsString::from("PATH"),
+            OsString::from_vec(b"SERVER.ENDPOINT.\xff".to_vec()),
+        ];
+
+        assert_eq!(
+            unknown_env_endpoints(names.into_iter()),
+            ["SERVER.ENDPOINT.NOT_AN_ENDPOINT: unknown endpoint"]
+        );
+    }
+
+    #[test]
+    fn mask_keeps_the_first_characters() {
+        assert_eq!(mask("0123456789abcdef"), "01234567...");
+        assert_eq!(mask("abc"), "abc...");
+        assert_eq!(mask("ключ-доступа"), "ключ-дос...");
+    }
+}
@@ -1,16 +1,35 @@
 use crate::{
+    auctions::{skyblock_auctions_search, skyblock_lowest_bin},
+    auth::{client_key, is_allowed},
+    batch::batch,
+    bazaar::skyblock_bazaar_history,
+    error::WebError,
+    extractors::{cached_player, resolve_player, MaybePlayer, ResolvedPlayer},
+    guild::{expand_members, uncached_members, Expand},
+    metrics::metrics,
+    mojang::{mojang_username, mojang_uuid, mojang_uuids},
+    nbt::{decode_auction_items, decode_inventories},
+    networth::skyblock_networth,
+    rate_limit::charge,
+    reload::reload_config,
+    sales::skyblock_prices,
+    skyblock::{fetch_profile, ProfileSelector},
+    stats::skyblock_stats,
     structs::{
-        AuctionQuery, GuildQuery, PlayerQuery, RecentGamesQuery, ResourcesPath,
-        SkyblockAuctionQuery, SkyblockBingoQuery, SkyblockProfileQuery, SkyblockProfilesQuery,
-        StatusQuery, WebData,
+        AuctionQuery, GuildQuery, PlayerQuery, ResourcesPath, SkyblockAuctionQuery,
+        SkyblockProfileQuery, SkyblockProfilesQuery, WebData,
     },
     utils::{bad_request, error_response, ok, HYPIXEL_ENDPOINTS},
 };
 use actix_web::{
     get,
+    http::StatusCode,
     web::{resource, Data, Path, Query, Redirect, ServiceConfig},
-    Responder,
+    HttpRequest, HttpResponse, Responder, ResponseError,
 };
+use rs_pixel::HypixelEndpoint;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 
 pub fn add_endpoint(config: &mut ServiceConfig, value: &str) {
     match value {
@@ -26,11 +45,17 @@ pub fn add_endpoint(config: &mut ServiceConfig, value: &str) {
         "SKYBLOCK_PROFILES" => config.service(skyblock_profiles),
         "SKYBLOCK_PROFILE" => config.service(skyblock_profile),
         "SKYBLOCK_BINGO" => config.service(skyblock_bingo),
-        "SKYBLOCK_NEWS" => config.service(skyblock_auction),
+        "SKYBLOCK_STATS" => config.service(skyblock_stats),
+        "SKYBLOCK_NETWORTH" => config.service(skyblock_networth),
+        "SKYBLOCK_NEWS" => config.service(skyblock_news),
         "SKYBLOCK_AUCTION" => config.service(skyblock_auction),
+        "SKYBLOCK_AUCTIONS_SEARCH" => config.service(skyblock_auctions_search),
         "SKYBLOCK_AUCTIONS" => config.service(skyblock_auctions),
         "SKYBLOCK_AUCTIONS_ENDED" => config.service(skyblock_auctions_ended),
+        "SKYBLOCK_BAZAAR_HISTORY" => config.service(skyblock_bazaar_history),
         "SKYBLOCK_BAZAAR" => config.service(skyblock_bazaar),
+        "SKYBLOCK_LOWEST_BIN" => config.service(skyblock_lowest_bin),
+        "SKYBLOCK_PRICES" => config.service(skyblock_prices),
         "SKYBLOCK_FIRESALES" => config.service(skyblock_fire_sales),
         "RESOURCES" => config.service(
             resource([
@@ -40,25 +65,124 @@ pub fn add_endpoint(config: &mut ServiceConfig, value: &str) {
             ])
             .to(resources),
         ),
+        "METRICS" => config.service(metrics),
+        "RELOAD" => config.service(reload_config),
+        "BATCH" => config.service(batch),
+        "MOJANG_UUID" => config.service(mojang_uuid),
+        "MOJANG_USERNAME" => config.service(mojang_username),
+        "MOJANG_UUIDS" => config.service(mojang_uuids),
         _ => panic!("Unable to parse server endpoint from {value}"),
     };
 }
 
+/// The Hypixel API path and parameters a request would be fetched with, if they are known without
+/// making a request. Must be kept in sync with the handlers below.
+pub fn hypixel_request(
+    web_data: &WebData,
+    path: &str,
+    query: &str,
+) -> Option<(String, HashMap<String, String>)> {
+    let uuid = || {
+        cached_player(
+            web_data,
+            &Query::<PlayerQuery>::from_query(query).ok()?.into_inner(),
+        )
+    };
+    let param = |name: &str, value: String| HashMap::from([(name.to_string(), value)]);
+
+    let (endpoint, params) = match path {
+        "/boosters" => (HypixelEndpoint::BOOSTERS, HashMap::new()),
+        "/leaderboards" => (HypixelEndpoint::LEADERBOARDS, HashMap::new()),
+        "/punishmentstats" => (HypixelEndpoint::PUNISHMENT_STATS, HashMap::new()),
+        "/player" => (HypixelEndpoint::PLAYER, param("uuid", uuid()?)),
+        "/guild" => {
+            let guild_query = Query::<GuildQuery>::from_query(query).ok()?.into_inner();
+            // Expanding members makes a request per member
+            if guild_query.expand.is_some() {
+                return None;
+            }
+            if let Some(id) = guild_query.id {
+                (HypixelEndpoint::GUILD, param("id", id))
+            } else if let Some(name) = guild_query.name {
+                (HypixelEndpoint::GUILD, param("name", name))
+            } else {
+                (HypixelEndpoint::GUILD, param("player", uuid()?))
+            }
+        }
+        "/counts" => (HypixelEndpoint::COUNTS, HashMap::new()),
+        "/status" => (HypixelEndpoint::STATUS, param("uuid", uuid()?)),
+        "/recentGames" => (HypixelEndpoint::RECENT_GAMES, param("uuid", uuid()?)),
+        "/skyblock/profiles" => (HypixelEndpoint::SKYBLOCK_PROFILES, param("uuid", uuid()?)),
+        "/skyblock/profile" => {
+            let profile_query = Query::<SkyblockProfileQuery>::from_query(query)
+                .ok()?
+                .into_inner();
+            match (profile_query.profile, profile_query.cute_name) {
+                (Some(profile), _) => {
+                    (HypixelEndpoint::SKYBLOCK_PROFILE, param("profile", profile))
+                }
+                // Profiles selected by name are picked from the profiles of the player
+                (None, Some(_)) => (HypixelEndpoint::SKYBLOCK_PROFILES, param("uuid", uuid()?)),
+                (None, None) => return None,
+            }
+        }
+        "/skyblock/bingo" => (HypixelEndpoint::SKYBLOCK_BINGO, param("uuid", uuid()?)),
+        "/skyblock/news" => (HypixelEndpoint::SKYBLOCK_NEWS, HashMap::new()),
+        "/skyblock/auction" => {
+            let auction = Query::<AuctionQuery>::from_query(query).ok()?.into_inner();
+            if auction.player.is_some() || auction.username.is_some() {
+                let player_query = PlayerQuery {
+                    uuid: None,
+                    player: auction.player,
+                    username: auction.username,
+                };
+                let uuid = cached_player(web_data, &player_query)?;
+                (HypixelEndpoint::SKYBLOCK_AUCTION, param("player", uuid))
+            } else if let Some(uuid) = auction.uuid {
+                (HypixelEndpoint::SKYBLOCK_AUCTION, param("uuid", uuid))
+            } else {
+                (
+                    HypixelEndpoint::SKYBLOCK_AUCTION,
+                    param("profile", auction.profile?),
+                )
+            }
+        }
+        "/skyblock/auctions" => {
+            let page = Query::<SkyblockAuctionQuery>::from_query(query)
+                .ok()?
+                .page
+                .unwrap_or(0);
+            (
+                HypixelEndpoint::SKYBLOCK_AUCTIONS,
+                param("page", page.to_string()),
+            )
+        }
+        "/skyblock/auctions_ended" => (HypixelEndpoint::SKYBLOCK_AUCTIONS_ENDED, HashMap::new()),
+        "/skyblock/bazaar" => (HypixelEndpoint::SKYBLOCK_BAZAAR, HashMap::new()),
+        "/skyblock/firesales" => (HypixelEndpoint::SKYBLOCK_FIRESALES, HashMap::new()),
+        path => {
+            let path = path.strip_prefix('/')?;
+            return HYPIXEL_ENDPOINTS
+                .iter()
+                .find(|(_, endpoint, resource)| *resource && endpoint.get_path() == path)
+                .map(|(_, endpoint, _)| (endpoint.get_path(), HashMap::new()));
+        }
+    };
+    Some((endpoint.get_path(), params))
+}
+
 pub async fn default() -> impl Responder {
     Redirect::to("https://github.com/kr45732/rs-pixel-backend").permanent()
 }
 
 #[get("/key")]
 async fn key(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_key().await {
-        Ok(res) => ok(res),
-        Err(err) => error_response(err),
-    }
+    HttpResponse::Ok().json(web_data.api.key_health())
 }
 
 #[get("/boosters")]
 async fn boosters(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_boosters().await {
+    match web_data.api.get_boosters().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -66,7 +190,7 @@ async fn boosters(web_data: Data<WebData>) -> impl Responder {
 
 #[get("/leaderboards")]
 async fn leaderboards(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_leaderboards().await {
+    match web_data.api.get_leaderboards().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -74,143 +198,98 @@ async fn leaderboards(web_data: Data<WebData>) -> impl Responder {
 
 #[get("/punishmentstats")]
 async fn punishment_stats(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_punishment_stats().await {
+    match web_data.api.get_punishment_stats().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
 }
 
 #[get("/player")]
-async fn player(web_data: Data<WebData>, query: Query<PlayerQuery>) -> impl Responder {
-    if query.username.is_none() && query.uuid.is_none() {
-        return bad_request("Missing one or more fields [username, uuid]");
-    }
-
-    let uuid;
-    if let Some(uuid_unwrap) = &query.uuid {
-        uuid = uuid_unwrap.to_string();
-    } else {
-        let username = query.username.clone().unwrap();
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .username_to_uuid(&username)
-            .await
-        {
-            Ok(res) => uuid = res.uuid,
-            Err(err) => return error_response(err),
-        }
-    }
-
-    match web_data.api.lock().unwrap().get_player(&uuid).await {
+async fn player(web_data: Data<WebData>, resolved: ResolvedPlayer) -> impl Responder {
+    match web_data.api.get_player(&resolved.uuid).await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
 }
 
 #[get("/guild")]
-async fn guild(web_data: Data<WebData>, query: Query<GuildQuery>) -> impl Responder {
+async fn guild(
+    web_data: Data<WebData>,
+    req: HttpRequest,
+    query: Query<GuildQuery>,
+    resolved: MaybePlayer,
+) -> impl Responder {
+    let expand = match query.expand.as_deref().map(Expand::parse) {
+        Some(None) => {
+            return bad_request("Invalid expand provided, expected members or members.status")
+        }
+        Some(expand) => expand,
+        None => None,
+    };
+    if expand == Some(Expand::MembersStatus)
+        && !is_allowed(
+            &web_data.config.load(),
+            client_key(&req).as_deref(),
+            "STATUS",
+        )
+    {
+        return WebError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "The status endpoint is disabled or cannot be used with this API key",
+        )
+        .error_response();
+    }
+
     let res;
     if let Some(id) = &query.id {
-        res = web_data.api.lock().unwrap().get_guild_by_id(id).await
+        res = web_data.api.get_guild_by_id(id).await
     } else if let Some(name) = &query.name {
-        res = web_data.api.lock().unwrap().get_guild_by_name(name).await
-    } else if query.player.is_some() || query.username.is_some() {
-        let uuid;
-        if let Some(uuid_unwrap) = &query.player {
-            uuid = uuid_unwrap.to_string();
-        } else {
-            let username = query.username.clone().unwrap();
-            match web_data
-                .api
-                .lock()
-                .unwrap()
-                .username_to_uuid(&username)
-                .await
-            {
-                Ok(res) => uuid = res.uuid,
-                Err(err) => return error_response(err),
+        res = web_data.api.get_guild_by_name(name).await
+    } else {
+        match resolved.resolve().await {
+            Ok(Some(resolved)) => res = web_data.api.get_guild_by_player(&resolved.uuid).await,
+            Ok(None) => {
+                return bad_request("Missing one or more fields [id, name, player, username]")
             }
+            Err(err) => return err.error_response(),
         }
-
-        res = web_data
-            .api
-            .lock()
-            .unwrap()
-            .get_guild_by_player(&uuid)
-            .await
-    } else {
-        return bad_request("Missing one or more fields [id, name, player, username]");
     }
 
     match res {
-        Ok(res) => ok(res),
+        Ok(res) => match expand {
+            Some(expand) => {
+                let uncached = uncached_members(&web_data, &res, expand).await;
+                if let Err(err) = charge(&web_data, &req, uncached) {
+                    return err.error_response();
+                }
+                HttpResponse::Ok().json(expand_members(&web_data, &res, expand).await)
+            }
+            None => ok(res),
+        },
         Err(err) => error_response(err),
     }
 }
 
 #[get("/counts")]
 async fn counts(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_counts().await {
+    match web_data.api.get_counts().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
 }
 
 #[get("/status")]
-async fn status(web_data: Data<WebData>, query: Query<StatusQuery>) -> impl Responder {
-    if query.username.is_none() && query.uuid.is_none() {
-        return bad_request("Missing one or more fields [username, uuid]");
-    }
-
-    let uuid;
-    if let Some(uuid_unwrap) = &query.uuid {
-        uuid = uuid_unwrap.to_string();
-    } else {
-        let username = query.username.clone().unwrap();
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .username_to_uuid(&username)
-            .await
-        {
-            Ok(res) => uuid = res.uuid,
-            Err(err) => return error_response(err),
-        }
-    }
-
-    match web_data.api.lock().unwrap().get_status(&uuid).await {
+async fn status(web_data: Data<WebData>, resolved: ResolvedPlayer) -> impl Responder {
+    match web_data.api.get_status(&resolved.uuid).await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
 }
 
 #[get("/recentGames")]
-async fn recent_games(web_data: Data<WebData>, query: Query<RecentGamesQuery>) -> impl Responder {
-    if query.username.is_none() && query.uuid.is_none() {
-        return bad_request("Missing one or more fields [username, uuid]");
-    }
-
-    let uuid;
-    if let Some(uuid_unwrap) = &query.uuid {
-        uuid = uuid_unwrap.to_string();
-    } else {
-        let username = query.username.clone().unwrap();
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .username_to_uuid(&username)
-            .await
-        {
-            Ok(res) => uuid = res.uuid,
-            Err(err) => return error_response(err),
-        }
-    }
-
-    match web_data.api.lock().unwrap().get_recent_games(&uuid).await {
+async fn recent_games(web_data: Data<WebData>, resolved: ResolvedPlayer) -> impl Responder {
+    match web_data.api.get_recent_games(&resolved.uuid).await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -220,35 +299,15 @@ async fn recent_games(web_data: Data<WebData>, query: Query<RecentGamesQuery>) -
 async fn skyblock_profiles(
     web_data: Data<WebData>,
     query: Query<SkyblockProfilesQuery>,
+    resolved: ResolvedPlayer,
 ) -> impl Responder {
-    if query.username.is_none() && query.uuid.is_none() {
-        return bad_request("Missing one or more fields [username, uuid]");
-    }
-
-    let uuid;
-    if let Some(uuid_unwrap) = &query.uuid {
-        uuid = uuid_unwrap.to_string();
-    } else {
-        let username = query.username.clone().unwrap();
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .username_to_uuid(&username)
-            .await
-        {
-            Ok(res) => uuid = res.uuid,
-            Err(err) => return error_response(err),
-        }
+    if let Some(profile) = &query.profile {
+        return profile_response(
+            fetch_profile(&web_data, &resolved.uuid, &ProfileSelector::parse(profile)).await,
+        );
     }
 
-    match web_data
-        .api
-        .lock()
-        .unwrap()
-        .get_skyblock_profiles(&uuid)
-        .await
-    {
+    match web_data.api.get_skyblock_profiles(&resolved.uuid).await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -258,50 +317,55 @@ async fn skyblock_profiles(
 async fn skyblock_profile(
     web_data: Data<WebData>,
     query: Query<SkyblockProfileQuery>,
+    resolved: MaybePlayer,
 ) -> impl Responder {
+    let decode = query.decode_inventories.unwrap_or(false);
     if let Some(profile) = &query.profile {
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .get_skyblock_profile(profile)
-            .await
-        {
+        match web_data.api.get_skyblock_profile(profile).await {
+            Ok(res) if decode => {
+                let mut res = json!(*res);
+                decode_inventories(&mut res["profile"]["members"]);
+                HttpResponse::Ok().json(res)
+            }
             Ok(res) => ok(res),
             Err(err) => error_response(err),
         }
+    } else if let Some(cute_name) = &query.cute_name {
+        let resolved =
+            match resolved.resolve().await {
+                Ok(Some(resolved)) => resolved,
+                Ok(None) => return bad_request(
+                    "Missing one or more fields [profile] or [cute_name, username, uuid, player]",
+                ),
+                Err(err) => return err.error_response(),
+            };
+        let res = fetch_profile(
+            &web_data,
+            &resolved.uuid,
+            &ProfileSelector::parse(cute_name),
+        )
+        .await;
+        profile_response(res.map(|mut profile| {
+            if decode {
+                decode_inventories(&mut profile["members"]);
+            }
+            profile
+        }))
     } else {
-        bad_request("Missing one or more fields [profile]")
+        bad_request("Missing one or more fields [profile] or [cute_name, username, uuid, player]")
     }
 }
 
-#[get("/skyblock/bingo")]
-async fn skyblock_bingo(
-    query: Query<SkyblockBingoQuery>,
-    web_data: Data<WebData>,
-) -> impl Responder {
-    if query.username.is_none() && query.uuid.is_none() {
-        return bad_request("Missing one or more fields [username, uuid]");
-    }
-
-    let uuid;
-    if let Some(uuid_unwrap) = &query.uuid {
-        uuid = uuid_unwrap.to_string();
-    } else {
-        let username = query.username.clone().unwrap();
-        match web_data
-            .api
-            .lock()
-            .unwrap()
-            .username_to_uuid(&username)
-            .await
-        {
-            Ok(res) => uuid = res.uuid,
-            Err(err) => return error_response(err),
-        }
+fn profile_response(res: Result<Value, WebError>) -> HttpResponse {
+    match res {
+        Ok(profile) => HttpResponse::Ok().json(json!({ "success": true, "profile": profile })),
+        Err(err) => err.error_response(),
     }
+}
 
-    match web_data.api.lock().unwrap().get_skyblock_bingo(&uuid).await {
+#[get("/skyblock/bingo")]
+async fn skyblock_bingo(web_data: Data<WebData>, resolved: ResolvedPlayer) -> impl Responder {
+    match web_data.api.get_skyblock_bingo(&resolved.uuid).await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -309,7 +373,7 @@ async fn skyblock_bingo(
 
 #[get("/skyblock/news")]
 async fn skyblock_news(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_skyblock_news().await {
+    match web_data.api.get_skyblock_news().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -317,45 +381,27 @@ async fn skyblock_news(web_data: Data<WebData>) -> impl Responder {
 
 #[get("/skyblock/auction")]
 async fn skyblock_auction(web_data: Data<WebData>, query: Query<AuctionQuery>) -> impl Responder {
-    let res;
-    if query.player.is_some() || query.username.is_some() {
-        let uuid;
-        if let Some(uuid_unwrap) = &query.player {
-            uuid = uuid_unwrap.to_string();
-        } else {
-            let username = query.username.clone().unwrap();
-            match web_data
-                .api
-                .lock()
-                .unwrap()
-                .username_to_uuid(&username)
-                .await
-            {
-                Ok(res) => uuid = res.uuid,
-                Err(err) => return error_response(err),
-            }
-        }
+    // The uuid parameter is the uuid of an auction so only player and username identify a player
+    let player_query = PlayerQuery {
+        uuid: None,
+        player: query.player.clone(),
+        username: query.username.clone(),
+    };
+    let resolved = match resolve_player(&web_data, &player_query).await {
+        Ok(resolved) => resolved,
+        Err(err) => return err.error_response(),
+    };
 
+    let res;
+    if let Some(resolved) = resolved {
         res = web_data
             .api
-            .lock()
-            .unwrap()
-            .get_skyblock_auction_by_player(&uuid)
+            .get_skyblock_auction_by_player(&resolved.uuid)
             .await
     } else if let Some(uuid) = &query.uuid {
-        res = web_data
-            .api
-            .lock()
-            .unwrap()
-            .get_skyblock_auction_by_uuid(uuid)
-            .await
+        res = web_data.api.get_skyblock_auction_by_uuid(uuid).await
     } else if let Some(profile) = &query.profile {
-        res = web_data
-            .api
-            .lock()
-            .unwrap()
-            .get_skyblock_auction_by_profile(profile)
-            .await
+        res = web_data.api.get_skyblock_auction_by_profile(profile).await
     } else {
         return bad_request("Missing one or more fields [player, uuid, profile, username]");
     }
@@ -373,11 +419,14 @@ async fn skyblock_auctions(
 ) -> impl Responder {
     match web_data
         .api
-        .lock()
-        .unwrap()
         .get_skyblock_auctions(query.page.unwrap_or(0))
         .await
     {
+        Ok(res) if query.decode_inventories.unwrap_or(false) => {
+            let mut res = json!(*res);
+            decode_auction_items(&mut res["auctions"]);
+            HttpResponse::Ok().json(res)
+        }
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -385,13 +434,7 @@ async fn skyblock_auctions(
 
 #[get("/skyblock/auctions_ended")]
 async fn skyblock_auctions_ended(web_data: Data<WebData>) -> impl Responder {
-    match web_data
-        .api
-        .lock()
-        .unwrap()
-        .get_skyblock_auctions_ended()
-        .await
-    {
+    match web_data.api.get_skyblock_auctions_ended().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -399,7 +442,7 @@ async fn skyblock_auctions_ended(web_data: Data<WebData>) -> impl Responder {
 
 #[get("/skyblock/bazaar")]
 async fn skyblock_bazaar(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_skyblock_bazaar().await {
+    match web_data.api.get_skyblock_bazaar().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -407,7 +450,7 @@ async fn skyblock_bazaar(web_data: Data<WebData>) -> impl Responder {
 
 #[get("/skyblock/firesales")]
 async fn skyblock_fire_sales(web_data: Data<WebData>) -> impl Responder {
-    match web_data.api.lock().unwrap().get_skyblock_fire_sales().await {
+    match web_data.api.get_skyblock_fire_sales().await {
         Ok(res) => ok(res),
         Err(err) => error_response(err),
     }
@@ -423,7 +466,7 @@ async fn resources(web_data: Data<WebData>, path: Path<ResourcesPath>) -> impl R
 
         for endpoint in HYPIXEL_ENDPOINTS {
             if endpoint.2 && endpoint.1.get_path() == resource_path {
-                return match web_data.api.lock().unwrap().get_resources(endpoint.1).await {
+                return match web_data.api.get_resources(endpoint.1).await {
                     Ok(res) => ok(res),
                     Err(err) => error_response(err),
                 };
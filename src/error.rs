@@ -0,0 +1,265 @@
+use crate::metrics::record_upstream_error;
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
+use rs_pixel::util::{error::Error, minecraft::ApiType};
+use serde::Serialize;
+use std::fmt;
+use tracing::warn;
+
+/// Retry-After (seconds) when an upstream rate limits us without saying for how long
+const DEFAULT_RETRY_AFTER: u64 = 60;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Upstream {
+    Hypixel,
+    Mojang,
+    Ashcon,
+    PlayerDb,
+}
+
+impl Upstream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Upstream::Hypixel => "hypixel",
+            Upstream::Mojang => "mojang",
+            Upstream::Ashcon => "ashcon",
+            Upstream::PlayerDb => "playerdb",
+        }
+    }
+}
+
+impl From<&ApiType> for Upstream {
+    fn from(api_type: &ApiType) -> Self {
+        match api_type {
+            ApiType::Mojang => Upstream::Mojang,
+            ApiType::Ashcon => Upstream::Ashcon,
+            ApiType::PlayerDb => Upstream::PlayerDb,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebError {
+    pub success: bool,
+    pub cause: String,
+    /// Stable machine-readable error code
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<Upstream>,
+    #[serde(skip)]
+    pub status: StatusCode,
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl WebError {
+    pub fn new(status: StatusCode, code: &'static str, cause: impl Into<String>) -> WebError {
+        WebError {
+            success: false,
+            cause: cause.into(),
+            code,
+            upstream: None,
+            status,
+            retry_after: None,
+        }
+    }
+
+    pub fn bad_request(cause: impl Into<String>) -> WebError {
+        WebError::new(StatusCode::BAD_REQUEST, "bad_request", cause)
+    }
+
+    /// Maps an error from a Hypixel API request
+    pub fn hypixel(err: Error) -> WebError {
+        WebError::upstream(err, Upstream::Hypixel)
+    }
+
+    /// Maps an error from a username or uuid conversion
+    pub fn minecraft(err: Error, api_type: &ApiType) -> WebError {
+        WebError::upstream(err, Upstream::from(api_type))
+    }
+
+    fn upstream(err: Error, upstream: Upstream) -> WebError {
+        let cause = err.to_string();
+        let (status, code, retry_after) = match err {
+            Error::RateLimit(time_till_reset) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                Some(time_till_reset.max(1) as u64),
+            ),
+            Error::Status(429, _) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "upstream_rate_limited",
+                Some(DEFAULT_RETRY_AFTER),
+            ),
+            // Mojang responds with 204 No Content for unknown usernames
+            Error::Status(204 | 404, _) if upstream != Upstream::Hypixel => {
+                (StatusCode::NOT_FOUND, "unknown_player", None)
+            }
+            Error::Status(404, _) => (StatusCode::NOT_FOUND, "not_found", None),
+            Error::Status(400, _) => (StatusCode::BAD_REQUEST, "bad_request", None),
+            Error::Status(403, _) if upstream == Upstream::Hypixel => {
+                (StatusCode::SERVICE_UNAVAILABLE, "invalid_api_key", None)
+            }
+            Error::Status(_, _) | Error::Unknown(_) => {
+                (StatusCode::BAD_GATEWAY, "upstream_error", None)
+            }
+            Error::Client(ref err)
+                if matches!(
+                    err.downcast_ref::<isahc::Error>(),
+                    Some(isahc::Error::Timeout)
+                ) =>
+            {
+                (StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", None)
+            }
+            Error::Client(_) => (StatusCode::BAD_GATEWAY, "upstream_unreachable", None),
+            Error::Parse(_) => (StatusCode::BAD_GATEWAY, "invalid_upstream_response", None),
+            Error::UnknownResource => (StatusCode::NOT_FOUND, "unknown_resource", None),
+        };
+
+        record_upstream_error(upstream.as_str(), code);
+        warn!(upstream = upstream.as_str(), code, "{cause}");
+        WebError {
+            upstream: Some(upstream),
+            retry_after,
+            ..WebError::new(status, code, cause)
+        }
+    }
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.cause)
+    }
+}
+
+impl ResponseError for WebError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status);
+        if let Some(retry_after) = self.retry_after {
+            res.insert_header((header::RETRY_AFTER, retry_after));
+        }
+        res.json(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::{json, Value};
+
+    fn client_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+        Error::Client(surf::Error::new(surf::StatusCode::InternalServerError, err))
+    }
+
+    fn parse_error() -> Error {
+        Error::Parse(serde_json::from_str::<Value>("{").unwrap_err())
+    }
+
+    #[test]
+    fn hypixel_errors_map_to_status_and_code() {
+        let cases = [
+            (Error::RateLimit(30), 429, "rate_limited"),
+            (
+                Error::Status(429, String::new()),
+                429,
+                "upstream_rate_limited",
+            ),
+            (Error::Status(404, String::new()), 404, "not_found"),
+            (Error::Status(400, String::new()), 400, "bad_request"),
+            (Error::Status(403, String::new()), 503, "invalid_api_key"),
+            (Error::Status(500, String::new()), 502, "upstream_error"),
+            (
+                Error::Unknown("unexpected".to_string()),
+                502,
+                "upstream_error",
+            ),
+            (client_error(isahc::Error::Timeout), 504, "upstream_timeout"),
+            (
+                client_error(isahc::Error::ConnectFailed),
+                502,
+                "upstream_unreachable",
+            ),
+            (parse_error(), 502, "invalid_upstream_response"),
+            (Error::UnknownResource, 404, "unknown_resource"),
+        ];
+        for (err, status, code) in cases {
+            let cause = err.to_string();
+            let err = WebError::hypixel(err);
+            assert_eq!((err.status.as_u16(), err.code), (status, code), "{cause}");
+            assert_eq!(err.upstream, Some(Upstream::Hypixel));
+            assert_eq!(err.cause, cause);
+        }
+    }
+
+    #[test]
+    fn minecraft_errors_map_to_status_and_code() {
+        let cases = [
+            (Error::Status(204, String::new()), 404, "unknown_player"),
+            (Error::Status(404, String::new()), 404, "unknown_player"),
+            (Error::Status(403, String::new()), 502, "upstream_error"),
+            (
+                Error::Status(429, String::new()),
+                429,
+                "upstream_rate_limited",
+            ),
+            (client_error(isahc::Error::Timeout), 504, "upstream_timeout"),
+        ];
+        for (err, status, code) in cases {
+            let err = WebError::minecraft(err, &ApiType::Ashcon);
+            assert_eq!((err.status.as_u16(), err.code), (status, code));
+            assert_eq!(err.upstream, Some(Upstream::Ashcon));
+        }
+    }
+
+    #[test]
+    fn rate_limits_set_retry_after() {
+        assert_eq!(
+            WebError::hypixel(Error::RateLimit(30)).retry_after,
+            Some(30)
+        );
+        // A quota that is being replenished is retried after at least a second
+        assert_eq!(WebError::hypixel(Error::RateLimit(0)).retry_after, Some(1));
+        assert_eq!(
+            WebError::hypixel(Error::Status(429, String::new())).retry_after,
+            Some(DEFAULT_RETRY_AFTER)
+        );
+        assert_eq!(
+            WebError::hypixel(Error::Status(500, String::new())).retry_after,
+            None
+        );
+    }
+
+    #[actix_web::test]
+    async fn errors_respond_with_json_and_retry_after() {
+        let res = WebError::hypixel(Error::RateLimit(30)).error_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "30");
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({
+                "success": false,
+                "cause": "Reached the rate limit; 30 seconds till reset",
+                "code": "rate_limited",
+                "upstream": "hypixel"
+            })
+        );
+
+        let res = WebError::bad_request("Invalid uuid provided").error_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(res.headers().get(header::RETRY_AFTER).is_none());
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({"success": false, "cause": "Invalid uuid provided", "code": "bad_request"})
+        );
+    }
+}
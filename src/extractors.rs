@@ -0,0 +1,228 @@
+use crate::{
+    error::WebError,
+    structs::{PlayerQuery, WebData},
+    utils::{is_valid_username, parse_uuid},
+};
+use actix_web::{
+    dev::Payload,
+    web::{Data, Query},
+    FromRequest, HttpRequest,
+};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+};
+
+/// A player resolved from the `uuid`, `player` (uuid or username), or `username` query parameters
+pub struct ResolvedPlayer {
+    /// Undashed lowercase uuid
+    pub uuid: String,
+}
+
+/// The player parameters of a request, only resolved once the handler needs the player so that
+/// parameters taking precedence over it (like the `id` of `/guild`) don't cost a request
+pub struct MaybePlayer {
+    web_data: Data<WebData>,
+    query: PlayerQuery,
+}
+
+impl MaybePlayer {
+    /// Like `ResolvedPlayer` but `None` when no player parameter was provided
+    pub async fn resolve(&self) -> Result<Option<ResolvedPlayer>, WebError> {
+        resolve_player(&self.web_data, &self.query).await
+    }
+}
+
+enum Player<'a> {
+    /// Undashed lowercase uuid
+    Uuid(String),
+    Username(&'a str),
+}
+
+fn parse_player(query: &PlayerQuery) -> Result<Option<Player<'_>>, WebError> {
+    if let Some(uuid) = &query.uuid {
+        return parse_uuid(uuid)
+            .map(|uuid| Some(Player::Uuid(uuid)))
+            .ok_or_else(|| WebError::bad_request("Invalid uuid provided"));
+    }
+
+    let username = if let Some(player) = &query.player {
+        if let Some(uuid) = parse_uuid(player) {
+            return Ok(Some(Player::Uuid(uuid)));
+        }
+        player
+    } else if let Some(username) = &query.username {
+        username
+    } else {
+        return Ok(None);
+    };
+
+    if !is_valid_username(username) {
+        return Err(WebError::bad_request("Invalid username provided"));
+    }
+    Ok(Some(Player::Username(username)))
+}
+
+pub async fn resolve_player(
+    web_data: &WebData,
+    query: &PlayerQuery,
+) -> Result<Option<ResolvedPlayer>, WebError> {
+    let username = match parse_player(query)? {
+        Some(Player::Uuid(uuid)) => return Ok(Some(ResolvedPlayer { uuid })),
+        Some(Player::Username(username)) => username,
+        None => return Ok(None),
+    };
+    let minecraft = &web_data.minecraft;
+    minecraft
+        .username_to_uuid(username, minecraft.apis())
+        .await
+        .map(|profile| Some(ResolvedPlayer { uuid: profile.uuid }))
+}
+
+/// The uuid of the player of a query if it can be resolved without a request
+pub fn cached_player(web_data: &WebData, query: &PlayerQuery) -> Option<String> {
+    match parse_player(query).ok()?? {
+        Player::Uuid(uuid) => Some(uuid),
+        Player::Username(username) => web_data.minecraft.cached_uuid(username),
+    }
+}
+
+fn extract(req: &HttpRequest) -> Result<MaybePlayer, WebError> {
+    let query = Query::<PlayerQuery>::from_query(req.query_string())
+        .map_err(|err| WebError::bad_request(err.to_string()))?;
+    Ok(MaybePlayer {
+        web_data: req.app_data::<Data<WebData>>().unwrap().clone(),
+        query: query.into_inner(),
+    })
+}
+
+impl FromRequest for MaybePlayer {
+    type Error = WebError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(extract(req))
+    }
+}
+
+impl FromRequest for ResolvedPlayer {
+    type Error = WebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let player = extract(req);
+
+        Box::pin(async move {
+            player?.resolve().await?.ok_or_else(|| {
+                WebError::bad_request("Missing one or more fields [username, uuid, player]")
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        endpoints::add_endpoint,
+        test_utils::{config, MockResponse, MockUpstream},
+    };
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use serde_json::{json, Value};
+
+    const MOJANG: &str = "api.mojang.com/users/profiles/minecraft/Notch";
+
+    fn get(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        mock.respond(
+            MOJANG,
+            MockResponse::ok(json!({"id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch"})),
+        );
+        mock.respond(
+            "api.hypixel.net/guild",
+            MockResponse::ok(json!({"success": true, "guild": null})),
+        );
+        mock
+    }
+
+    #[actix_web::test]
+    async fn players_are_not_resolved_when_an_id_is_given() {
+        let mock = mock();
+        let app = init_service(
+            App::new()
+                .app_data(mock.web_data(config("")).await)
+                .configure(|conf| add_endpoint(conf, "GUILD")),
+        )
+        .await;
+
+        for uri in [
+            "/guild?id=5eba1c5f8ea8c960a61f38ed&username=Notch",
+            "/guild?name=Test&username=an-invalid-username",
+        ] {
+            let res = call_service(&app, get(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        }
+        assert_eq!(mock.hits(MOJANG), 0);
+    }
+
+    #[actix_web::test]
+    async fn players_are_resolved_when_needed() {
+        let mock = mock();
+        let app = init_service(
+            App::new()
+                .app_data(mock.web_data(config("")).await)
+                .configure(|conf| add_endpoint(conf, "GUILD")),
+        )
+        .await;
+
+        let res = call_service(&app, get("/guild?username=Notch").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(mock.hits(MOJANG), 1);
+
+        let res = call_service(
+            &app,
+            get("/guild?username=an-invalid-username").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["cause"], "Invalid username provided");
+
+        let res = call_service(&app, get("/guild").to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn required_players_are_validated() {
+        let mock = mock();
+        let app = init_service(
+            App::new()
+                .app_data(mock.web_data(config("")).await)
+                .configure(|conf| add_endpoint(conf, "STATUS")),
+        )
+        .await;
+
+        for (uri, cause) in [
+            (
+                "/status",
+                "Missing one or more fields [username, uuid, player]",
+            ),
+            ("/status?uuid=not-a-uuid", "Invalid uuid provided"),
+        ] {
+            let res = call_service(&app, get(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: Value = read_body_json(res).await;
+            assert_eq!(body["cause"], cause);
+        }
+        assert_eq!(mock.hits(MOJANG), 0);
+    }
+}
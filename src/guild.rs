@@ -0,0 +1,199 @@
+use crate::{error::WebError, structs::WebData};
+use futures::{future::join_all, stream, StreamExt};
+use rs_pixel::{response::guild_response::GuildResponse, HypixelEndpoint};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Extra member fields of a `/guild` response
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Expand {
+    /// The current username of every member
+    Members,
+    /// The username and the online status of every member
+    MembersStatus,
+}
+
+impl Expand {
+    pub fn parse(expand: &str) -> Option<Expand> {
+        match expand {
+            "members" => Some(Expand::Members),
+            "members.status" => Some(Expand::MembersStatus),
+            _ => None,
+        }
+    }
+}
+
+/// Members whose fields aren't all cached, each of them takes a request from the rate limit quota
+pub async fn uncached_members(web_data: &WebData, res: &GuildResponse, expand: Expand) -> usize {
+    let Some(guild) = &res.guild else {
+        return 0;
+    };
+
+    let status_path = HypixelEndpoint::STATUS.get_path();
+    let uncached = guild.members.iter().map(|member| async {
+        if web_data.minecraft.cached_username(&member.uuid).is_none() {
+            return true;
+        }
+        let params = HashMap::from([("uuid".to_string(), member.uuid.clone())]);
+        expand == Expand::MembersStatus && !web_data.api.is_cached(&status_path, &params).await
+    });
+    join_all(uncached)
+        .await
+        .into_iter()
+        .filter(|uncached| *uncached)
+        .count()
+}
+
+/// Adds the `username` (and `status`) of every member to a guild, `EXPAND_CONCURRENCY` members at a time.
+/// Fields that couldn't be resolved are null and listed in `expand_errors`.
+pub async fn expand_members(web_data: &WebData, res: &GuildResponse, expand: Expand) -> Value {
+    let mut value = json!(res);
+    let Some(guild) = &res.guild else {
+        return value;
+    };
+
+    let concurrency = web_data.config.load().expand_concurrency;
+    let expanded = stream::iter(&guild.members)
+        .map(|member| expand_member(web_data, &member.uuid, expand))
+        .buffered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut errors = Vec::new();
+    if let Some(members) = value["guild"]["members"].as_array_mut() {
+        for (member, fields) in members.iter_mut().zip(expanded) {
+            let uuid = member["uuid"].clone();
+            for (field, res) in fields {
+                member[field] = res.unwrap_or_else(|err| {
+                    errors.push(json!({
+                        "uuid": uuid,
+                        "field": field,
+                        "code": err.code,
+                        "cause": err.cause,
+                    }));
+                    Value::Null
+                });
+            }
+        }
+    }
+    value["expand_errors"] = json!(errors);
+    value
+}
+
+async fn expand_member(
+    web_data: &WebData,
+    uuid: &str,
+    expand: Expand,
+) -> Vec<(&'static str, Result<Value, WebError>)> {
+    let mut fields = vec![(
+        "username",
+        web_data
+            .minecraft
+            .uuid_to_username(uuid, web_data.minecraft.apis())
+            .await
+            .map(|profile| Value::from(profile.username)),
+    )];
+    if expand == Expand::MembersStatus {
+        let status = web_data
+            .api
+            .get_status(uuid)
+            .await
+            .map(|res| json!(res.session))
+            .map_err(WebError::hypixel);
+        fields.push(("status", status));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+    };
+    use serde_json::json;
+
+    const MEMBERS: [&str; 3] = [
+        "00000000000000000000000000000001",
+        "00000000000000000000000000000002",
+        "00000000000000000000000000000003",
+    ];
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        let members = MEMBERS
+            .map(|uuid| json!({"uuid": uuid, "rank": "Member", "joined": 0, "expHistory": {}}));
+        mock.respond(
+            "api.hypixel.net/guild",
+            MockResponse::ok(json!({
+                "success": true,
+                "guild": {
+                    "_id": "guild",
+                    "created": 0,
+                    "exp": 0,
+                    "name": "Guild",
+                    "tag": "G",
+                    "tagColor": "GOLD",
+                    "members": members,
+                    "ranks": [],
+                    "preferredGames": [],
+                    "guildExpByGameType": {},
+                    "achievements": {},
+                    "coins": 0,
+                    "coinsEver": 0
+                }
+            })),
+        );
+        mock.respond(
+            "api.hypixel.net/status",
+            MockResponse::ok(json!({"success": true, "session": {"online": false}})),
+        );
+        for (idx, uuid) in MEMBERS.iter().enumerate() {
+            mock.respond(
+                &format!("sessionserver.mojang.com/session/minecraft/profile/{uuid}"),
+                MockResponse::ok(json!({"id": uuid, "name": format!("Member{idx}")})),
+            );
+        }
+        mock
+    }
+
+    fn settings(burst: u32) -> String {
+        format!(
+            "MINECRAFT_API_TYPE = \"Mojang\"\nMINECRAFT_CACHE_TTL = 60\n\
+            [HYPIXEL_CACHE_TTL]\nSTATUS = 60\n\
+            [SERVER]\nPERIOD = 60000\nBURST = {burst}\n\
+            [SERVER.ENDPOINT]\nGUILD = true\nSTATUS = true"
+        )
+    }
+
+    fn expand() -> TestRequest {
+        TestRequest::get()
+            .uri("/guild?id=guild&expand=members.status")
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn expanding_members_takes_from_the_quota() {
+        let mock = mock();
+        let app = init_service(crate::app(mock.web_data(config(&settings(3))).await)).await;
+
+        let res = call_service(&app, expand().to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(mock.hits("api.hypixel.net/status"), 0);
+    }
+
+    #[actix_web::test]
+    async fn cached_members_are_free() {
+        let mock = mock();
+        let app = init_service(crate::app(mock.web_data(config(&settings(5))).await)).await;
+
+        let res = call_service(&app, expand().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(mock.hits("api.hypixel.net/status"), 3);
+
+        let res = call_service(&app, expand().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(mock.hits("api.hypixel.net/status"), 3);
+    }
+}
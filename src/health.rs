@@ -0,0 +1,230 @@
+use crate::structs::{ComponentStatus, ReadyComponents, ReadyResponse, WebData};
+use actix_web::{get, web::Data, HttpResponse, Responder};
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use rs_pixel::util::utils::get_timestamp_millis;
+use serde_json::json;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Username resolved to check that the Minecraft API is reachable
+const PROBE_USERNAME: &str = "Notch";
+
+type Check = Shared<BoxFuture<'static, Arc<ReadyResponse>>>;
+
+#[derive(Default)]
+struct ReadinessState {
+    last: Option<(Instant, Arc<ReadyResponse>)>,
+    /// Check in progress, awaited by every probe that arrives before it completes
+    pending: Option<Check>,
+}
+
+/// Caches the result of the readiness checks so that frequent probes don't reach the upstream APIs
+pub struct Readiness {
+    interval: Duration,
+    state: Mutex<ReadinessState>,
+}
+
+impl Readiness {
+    pub fn new(interval: Duration) -> Readiness {
+        Readiness {
+            interval,
+            state: Mutex::new(ReadinessState::default()),
+        }
+    }
+}
+
+impl<E: fmt::Display> From<Result<(), E>> for ComponentStatus {
+    fn from(res: Result<(), E>) -> Self {
+        match res {
+            Ok(()) => ComponentStatus {
+                healthy: true,
+                cause: None,
+            },
+            Err(err) => ComponentStatus {
+                healthy: false,
+                cause: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+async fn check(web_data: &WebData) -> ReadyResponse {
+    // Keys are checked by the responses to every request, so this neither waits for nor uses quota
+    let hypixel = if web_data.api.key_health().iter().any(|key| key.valid) {
+        Ok(())
+    } else {
+        Err("No valid API key")
+    };
+
+    let minecraft = web_data.minecraft.probe(PROBE_USERNAME).await;
+
+    let components = ReadyComponents {
+        // The server only starts once the config has been parsed
+        config: ComponentStatus::from(Ok::<(), String>(())),
+        hypixel: ComponentStatus::from(hypixel),
+        minecraft: ComponentStatus::from(minecraft),
+        cache: ComponentStatus::from(web_data.api.ping_cache().await),
+    };
+    let healthy = [
+        &components.config,
+        &components.hypixel,
+        &components.minecraft,
+        &components.cache,
+    ]
+    .iter()
+    .all(|component| component.healthy);
+
+    ReadyResponse {
+        success: healthy,
+        checked_at: get_timestamp_millis(),
+        components,
+    }
+}
+
+#[get("/health")]
+pub async fn health() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "success": true }))
+}
+
+#[get("/ready")]
+pub async fn ready(web_data: Data<WebData>) -> impl Responder {
+    let pending = {
+        let mut state = web_data.readiness.state.lock().unwrap();
+        if let Some((checked, res)) = &state.last {
+            if checked.elapsed() < web_data.readiness.interval {
+                return respond(res);
+            }
+        }
+
+        // Concurrent probes wait for the check in progress instead of starting their own
+        let web_data = web_data.clone();
+        state
+            .pending
+            .get_or_insert_with(|| {
+                async move {
+                    let res = Arc::new(check(&web_data).await);
+                    let mut state = web_data.readiness.state.lock().unwrap();
+                    state.last = Some((Instant::now(), res.clone()));
+                    state.pending = None;
+                    res
+                }
+                .boxed()
+                .shared()
+            })
+            .clone()
+    };
+
+    respond(&*pending.await)
+}
+
+fn respond(res: &ReadyResponse) -> HttpResponse {
+    if res.success {
+        HttpResponse::Ok().json(res)
+    } else {
+        HttpResponse::ServiceUnavailable().json(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+    };
+    use futures::future::join;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    const MOJANG: &str = "api.mojang.com/users/profiles/minecraft/Notch";
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        mock.respond(
+            MOJANG,
+            MockResponse::ok(json!({"id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch"}))
+                .delay(Duration::from_millis(50)),
+        );
+        mock
+    }
+
+    fn settings(interval: u64) -> String {
+        format!("MINECRAFT_API_TYPE = \"Mojang\"\nREADY_CHECK_INTERVAL = {interval}")
+    }
+
+    fn get(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn health_responds_while_running() {
+        let mock = mock();
+        let app = init_service(crate::app(mock.web_data(config("")).await)).await;
+
+        let res = call_service(&app, get("/health").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(mock.hits(MOJANG), 0);
+    }
+
+    #[actix_web::test]
+    async fn ready_checks_are_cached_for_the_interval() {
+        let mock = mock();
+        let app = init_service(crate::app(mock.web_data(config(&settings(60))).await)).await;
+
+        let (first, second) = join(
+            call_service(&app, get("/ready").to_request()),
+            call_service(&app, get("/ready").to_request()),
+        )
+        .await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        let res = call_service(&app, get("/ready").to_request()).await;
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["components"]["minecraft"]["healthy"], true);
+
+        assert_eq!(mock.hits(MOJANG), 1);
+        // Readiness doesn't use the quota of the API key
+        assert_eq!(mock.hits("api.hypixel.net/key"), 1);
+    }
+
+    #[actix_web::test]
+    async fn ready_checks_again_after_the_interval() {
+        let mock = mock();
+        let app = init_service(crate::app(mock.web_data(config(&settings(0))).await)).await;
+
+        call_service(&app, get("/ready").to_request()).await;
+        call_service(&app, get("/ready").to_request()).await;
+        assert_eq!(mock.hits(MOJANG), 2);
+    }
+
+    #[actix_web::test]
+    async fn failing_components_are_reported() {
+        let mock = mock();
+        mock.respond(
+            "api.hypixel.net/key",
+            MockResponse::status(403, json!({"success": false, "cause": "Invalid API key"})),
+        );
+        mock.respond(
+            MOJANG,
+            MockResponse::status(500, json!({"error": "Internal Server Error"})),
+        );
+        let app = init_service(crate::app(mock.web_data(config(&settings(60))).await)).await;
+
+        let res = call_service(&app, get("/ready").to_request()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["success"], false);
+        assert_eq!(body["components"]["hypixel"]["healthy"], false);
+        assert_eq!(body["components"]["minecraft"]["healthy"], false);
+        assert_eq!(body["components"]["cache"]["healthy"], true);
+    }
+}
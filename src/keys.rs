@@ -0,0 +1,277 @@
+use crate::{config::mask, structs::KeyHealth};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use surf::{
+    middleware::{Middleware, Next},
+    utils::async_trait,
+    Client, Request, Response,
+};
+use tracing::warn;
+
+#[derive(Default)]
+struct KeyState {
+    remaining: Option<i64>,
+    limit: Option<i64>,
+    reset: Option<Instant>,
+    invalid: bool,
+}
+
+impl KeyState {
+    fn remaining(&self) -> Option<i64> {
+        if self.invalid {
+            None
+        } else if self.reset.is_none_or(|reset| reset <= Instant::now()) {
+            // The quota has been replenished (or was never used)
+            Some(self.limit.unwrap_or(i64::MAX))
+        } else {
+            Some(self.remaining.unwrap_or(i64::MAX))
+        }
+    }
+}
+
+/// Tracks the quota of every Hypixel API key from the rate limit headers of its responses.
+/// Used as a surf middleware on the client shared by all `RsPixel` instances, the quota
+/// of a key is reserved through `ApiPool::client` before each request.
+#[derive(Clone)]
+pub struct KeyTracker {
+    keys: Arc<Mutex<HashMap<String, KeyState>>>,
+}
+
+impl KeyTracker {
+    pub fn new(keys: &[String]) -> KeyTracker {
+        KeyTracker {
+            keys: Arc::new(Mutex::new(
+                keys.iter()
+                    .map(|key| (key.clone(), KeyState::default()))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Remaining quota of a key or `None` if it has been evicted
+    pub fn remaining(&self, key: &str) -> Option<i64> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(KeyState::remaining)
+    }
+
+    pub fn is_valid(&self, key: &str) -> bool {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|state| !state.invalid)
+    }
+
+    pub fn has_valid(&self) -> bool {
+        self.keys
+            .lock()
+            .unwrap()
+            .values()
+            .any(|state| !state.invalid)
+    }
+
+    /// Takes a request from the quota of a key, which is shared by every client of the key.
+    /// Fails with the time until the quota is replenished once it is exhausted.
+    pub fn reserve(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        if let Some(state) = self.keys.lock().unwrap().get_mut(key) {
+            if let (Some(reset), Some(remaining)) = (state.reset, state.remaining) {
+                if reset > now {
+                    if remaining <= 0 {
+                        return Err(reset - now);
+                    }
+                    state.remaining = Some(remaining - 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn evict(&self, key: &str) {
+        if let Some(state) = self.keys.lock().unwrap().get_mut(key) {
+            state.invalid = true;
+        }
+    }
+
+    pub fn health(&self) -> Vec<KeyHealth> {
+        let now = Instant::now();
+        let mut health = self
+            .keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, state)| KeyHealth {
+                key: mask(key),
+                valid: !state.invalid,
+                remaining: state.remaining().filter(|&v| v != i64::MAX),
+                limit: state.limit,
+                time_till_reset: state
+                    .reset
+                    .filter(|&reset| reset > now)
+                    .map(|reset| (reset - now).as_secs()),
+            })
+            .collect::<Vec<_>>();
+        health.sort_by(|a, b| a.key.cmp(&b.key));
+        health
+    }
+
+    fn update(&self, key: &str, res: &Response) {
+        let header = |name: &str| {
+            res.header(name)
+                .and_then(|header| header.as_str().parse::<i64>().ok())
+        };
+
+        if let Some(state) = self.keys.lock().unwrap().get_mut(key) {
+            if let Some(limit) = header("RateLimit-Limit") {
+                state.limit = Some(limit);
+            }
+            if let Some(remaining) = header("RateLimit-Remaining") {
+                state.remaining = Some(remaining);
+            }
+            if let Some(time_till_reset) = header("RateLimit-Reset") {
+                state.reset =
+                    Some(Instant::now() + Duration::from_secs(time_till_reset.max(0) as u64));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for KeyTracker {
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result<Response> {
+        let key = req
+            .header("API-Key")
+            .map(|header| header.as_str().to_string());
+        let mut res = next.run(req, client).await?;
+
+        if let Some(key) = key {
+            self.update(&key, &res);
+            // Every `RsPixel` keeps its own copy of the quota and blocks its thread once it runs out,
+            // so the quota is only tracked here and the clients never see it
+            res.remove_header("RateLimit-Limit");
+            res.remove_header("RateLimit-Remaining");
+            res.remove_header("RateLimit-Reset");
+
+            if res.status() == 403 {
+                let body = res.body_string().await?;
+                if body.contains("Invalid API key") {
+                    warn!("Evicting invalid API key {}", mask(&key));
+                    self.evict(&key);
+                }
+                res.set_body(body);
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use serde_json::json;
+    use surf::http;
+
+    fn response(headers: &[(&str, i64)]) -> Response {
+        let mut res = http::Response::new(200);
+        for (name, value) in headers {
+            res.insert_header(*name, value.to_string());
+        }
+        Response::from(res)
+    }
+
+    #[test]
+    fn reserving_stops_at_the_remaining_quota_until_the_reset() {
+        let keys = KeyTracker::new(&["key".to_string()]);
+        assert!(keys.reserve("key").is_ok());
+        assert_eq!(keys.remaining("key"), Some(i64::MAX));
+
+        keys.update(
+            "key",
+            &response(&[
+                ("RateLimit-Limit", 300),
+                ("RateLimit-Remaining", 2),
+                ("RateLimit-Reset", 60),
+            ]),
+        );
+        assert_eq!(keys.remaining("key"), Some(2));
+        assert!(keys.reserve("key").is_ok());
+        assert!(keys.reserve("key").is_ok());
+        let wait = keys.reserve("key").unwrap_err();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+        assert_eq!(keys.remaining("key"), Some(0));
+    }
+
+    #[test]
+    fn the_quota_is_replenished_after_the_reset() {
+        let keys = KeyTracker::new(&["key".to_string()]);
+        keys.update(
+            "key",
+            &response(&[
+                ("RateLimit-Limit", 300),
+                ("RateLimit-Remaining", 0),
+                ("RateLimit-Reset", 0),
+            ]),
+        );
+        assert_eq!(keys.remaining("key"), Some(300));
+        assert!(keys.reserve("key").is_ok());
+    }
+
+    #[test]
+    fn health_masks_keys_and_reports_the_reset() {
+        let keys = KeyTracker::new(&["0123456789abcdef".to_string(), "é".repeat(10)]);
+        keys.update(
+            "0123456789abcdef",
+            &response(&[
+                ("RateLimit-Limit", 300),
+                ("RateLimit-Remaining", 10),
+                ("RateLimit-Reset", 30),
+            ]),
+        );
+        keys.evict(&"é".repeat(10));
+
+        let health = keys.health();
+        assert_eq!(health[0].key, "01234567...");
+        assert_eq!(health[0].remaining, Some(10));
+        assert_eq!(health[0].limit, Some(300));
+        assert!(health[0].time_till_reset.is_some_and(|secs| secs <= 30));
+        assert_eq!(health[1].key, format!("{}...", "é".repeat(8)));
+        assert!(!health[1].valid);
+        assert_eq!(health[1].remaining, None);
+        assert!(keys.has_valid());
+    }
+
+    #[actix_web::test]
+    async fn keys_rejected_by_hypixel_are_evicted() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::status(403, json!({"success": false, "cause": "Invalid API key"})),
+        );
+        let pool = mock.api_pool(&config("")).await;
+        assert!(pool.key_health()[0].valid);
+
+        assert!(pool.get_counts().await.is_err());
+        assert!(!pool.key_health()[0].valid);
+    }
+
+    #[actix_web::test]
+    async fn other_forbidden_responses_keep_the_key() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::status(403, json!({"success": false, "cause": "Forbidden"})),
+        );
+        let pool = mock.api_pool(&config("")).await;
+
+        assert!(pool.get_counts().await.is_err());
+        assert!(pool.key_health()[0].valid);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{
+    auth::client_key,
+    client_ip::client_ip,
+    config::{ClientKey, Config, LogFormat},
+    metrics::record_upstream,
+    structs::WebData,
+};
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    web::Data,
+    Error,
+};
+use std::{cell::Cell, future::Future, time::Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Per-request state filled in while the request is handled
+#[derive(Default)]
+struct RequestContext {
+    cache_hits: Cell<u32>,
+    cache_misses: Cell<u32>,
+}
+
+impl RequestContext {
+    fn cache(&self) -> &'static str {
+        match (self.cache_hits.get(), self.cache_misses.get()) {
+            (0, 0) => "none",
+            (_, 0) => "hit",
+            _ => "miss",
+        }
+    }
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+/// Sets up the global subscriber from the `LOG_LEVEL` and `LOG_FORMAT` settings
+pub fn init(config: &Config) {
+    // The level is validated when the config is loaded
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(&config.log_level));
+
+    match config.log_format {
+        LogFormat::Json => subscriber.json().with_span_list(true).init(),
+        LogFormat::Pretty => subscriber.init(),
+    }
+}
+
+/// Marks a cache lookup of the current request as a hit or a miss for its access log entry
+pub fn record_request_cache(hit: bool) {
+    let _ = REQUEST.try_with(|context| {
+        let counter = if hit {
+            &context.cache_hits
+        } else {
+            &context.cache_misses
+        };
+        counter.set(counter.get() + 1);
+    });
+}
+
+/// Access log entry of a request, identified by its `X-Request-Id` (generated if the client didn't send one)
+pub struct AccessLog {
+    request_id: String,
+    method: String,
+    path: String,
+    client_ip: String,
+    /// Name of the client key, if any
+    client: String,
+}
+
+impl AccessLog {
+    pub fn new(req: &ServiceRequest) -> AccessLog {
+        let config = req.app_data::<Data<WebData>>().unwrap().config.load();
+        AccessLog {
+            request_id: req
+                .headers()
+                .get(REQUEST_ID)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| !value.is_empty() && value.len() <= 128)
+                .map(str::to_string)
+                .unwrap_or_else(|| Uuid::new_v4().simple().to_string()),
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            client_ip: client_ip(req.request(), &config)
+                .map(|client| client.to_string())
+                .unwrap_or_default(),
+            client: client_key(req.request())
+                .and_then(|key| config.client_key(&key).map(ClientKey::label))
+                .unwrap_or_else(|| "anonymous".to_string()),
+        }
+    }
+
+    /// Runs the request in a span carrying its id and writes the entry once it completes
+    pub async fn run<B>(
+        self,
+        fut: impl Future<Output = Result<ServiceResponse<B>, Error>>,
+    ) -> Result<ServiceResponse<B>, Error> {
+        let AccessLog {
+            request_id,
+            method,
+            path,
+            client_ip,
+            client,
+        } = self;
+        let span = info_span!("request", request_id = %request_id);
+
+        async move {
+            let start = Instant::now();
+            let (res, cache) = REQUEST
+                .scope(RequestContext::default(), async {
+                    let res = fut.await;
+                    (res, REQUEST.with(RequestContext::cache))
+                })
+                .await;
+            let mut res = res?;
+
+            let route = res
+                .request()
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".to_string());
+            let status = res.status().as_u16();
+            let latency_ms = start.elapsed().as_millis() as u64;
+            if res.status().is_server_error() {
+                warn!(%method, %path, %route, status, latency_ms, cache, %client_ip, %client, "request failed");
+            } else {
+                info!(%method, %path, %route, status, latency_ms, cache, %client_ip, %client, "request");
+            }
+
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut().insert(REQUEST_ID, value);
+            }
+            Ok(res)
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Runs a request to an upstream API in its own span and records its latency
+pub async fn upstream<F: Future>(upstream: &'static str, endpoint: &str, fut: F) -> F::Output {
+    let start = Instant::now();
+    let res = fut
+        .instrument(info_span!("upstream", upstream, endpoint))
+        .await;
+    record_upstream(upstream, endpoint, start);
+    debug!(
+        upstream,
+        endpoint,
+        latency_ms = start.elapsed().as_millis() as u64,
+        "upstream request"
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use serde_json::{json, Value};
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    /// Log lines written by the subscriber of the current thread
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    fn get(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn requests_are_logged_with_their_id() {
+        let capture = Capture::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_env_filter("rs_pixel_backend=debug")
+                .json()
+                .with_span_list(true)
+                .with_writer({
+                    let capture = capture.clone();
+                    move || capture.clone()
+                })
+                .finish(),
+        );
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::ok(json!({"success": true, "games": {}, "playerCount": 0})),
+        );
+        let web_data = mock
+            .web_data(config(
+                "[HYPIXEL_CACHE_TTL]\nCOUNTS = 60\n[SERVER.ENDPOINT]\nCOUNTS = true",
+            ))
+            .await;
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(
+            &app,
+            get("/counts")
+                .insert_header(("x-request-id", "first-request"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.headers().get("x-request-id").unwrap(), "first-request");
+        let res = call_service(&app, get("/counts").to_request()).await;
+        let generated = res.headers().get("x-request-id").unwrap().to_str().unwrap();
+        assert_eq!(generated.len(), 32);
+
+        let lines = capture.lines();
+        let upstream = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "upstream request")
+            .unwrap();
+        assert_eq!(upstream["span"]["request_id"], "first-request");
+        assert_eq!(upstream["fields"]["upstream"], "hypixel");
+        assert_eq!(upstream["fields"]["endpoint"], "counts");
+
+        let requests = lines
+            .iter()
+            .filter(|line| line["fields"]["message"] == "request")
+            .collect::<Vec<_>>();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["span"]["request_id"], "first-request");
+        assert_eq!(requests[0]["fields"]["route"], "/counts");
+        assert_eq!(requests[0]["fields"]["status"], 200);
+        assert_eq!(requests[0]["fields"]["cache"], "miss");
+        assert_eq!(requests[0]["fields"]["client"], "anonymous");
+        assert_eq!(requests[1]["span"]["request_id"], generated);
+        assert_eq!(requests[1]["fields"]["cache"], "hit");
+    }
+}
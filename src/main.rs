@@ -1,191 +1,229 @@
+mod api;
+mod auctions;
+mod auth;
+mod batch;
+mod bazaar;
+mod cache;
+mod client_ip;
+mod config;
 mod endpoints;
+mod error;
+mod extractors;
+mod guild;
+mod health;
+mod keys;
+mod logging;
+mod metrics;
+mod minecraft;
+mod mojang;
+mod nbt;
+mod networth;
+mod prices;
+mod rate_limit;
+mod reload;
+mod sales;
+mod skyblock;
+mod stats;
 mod structs;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
 use crate::{
+    api::ApiPool,
+    auctions::{update_auctions, AuctionHouse},
+    auth::Access,
+    bazaar::{record_bazaar, BazaarHistory},
+    cache::{CacheBackend, MemoryCache, RedisCache},
+    config::{Args, CacheBackendType, Config},
     endpoints::{add_endpoint, default},
+    health::Readiness,
+    keys::KeyTracker,
+    logging::AccessLog,
+    metrics::record_request,
+    minecraft::Resolver,
+    rate_limit::{RateLimits, ReloadableGovernor},
+    sales::{record_sales, Sales},
     structs::WebData,
-    utils::{HYPIXEL_ENDPOINTS, SERVER_ENDPOINTS},
+    utils::SERVER_ENDPOINTS,
 };
-use actix_governor::{Governor, GovernorConfigBuilder, KeyExtractor, SimpleKeyExtractionError};
 use actix_web::{
-    dev::ServiceRequest,
+    body::MessageBody,
+    dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse},
     web::{self, Data},
-    App, HttpServer,
+    App, HttpServer, Responder, ResponseError,
 };
+use arc_swap::ArcSwap;
 use dotenv::dotenv;
-use rs_pixel::{util::minecraft::ApiType, ConfigBuilder, RateLimitStrategy, RsPixel};
-use std::{collections::HashMap, env, error::Error, sync::Mutex, time::Duration};
+use std::{
+    env,
+    error::Error,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+use tracing::info;
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    println!("Reading config...");
-    if dotenv().is_err() {
-        println!("Cannot find a .env file, will attempt to use environment variables");
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(2);
+        }
+    };
+    let dotenv = dotenv();
+    let config_file = args
+        .config
+        .or_else(|| env::var("CONFIG_FILE").ok().map(PathBuf::from));
+    let config = match Config::load(config_file.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+    if args.check_config {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        return Ok(());
     }
 
-    let api_key = env::var("API_KEY")
-        .unwrap_or_else(|_| panic!("Unable to find API_KEY environment variable"));
-    let port = env::var("PORT")
-        .ok()
-        .and_then(|v| v.parse::<u16>().ok())
-        .unwrap_or_else(|| panic!("Unable to find PORT environment variable"));
-    let base_url = env::var("BASE_URL")
-        .unwrap_or_else(|_| panic!("Unable to find BASE_URL environment variable"));
-
-    let mut config = ConfigBuilder::default().client(
-        surf::Config::new()
-            .set_timeout(Some(Duration::from_secs(15)))
-            .try_into()?,
-    );
-
-    if let Ok(minecraft_api_type) = env::var("MINECRAFT_API_TYPE") {
-        config = config.minecraft_api_type(match minecraft_api_type.as_str() {
-            "Mojang" => ApiType::Mojang,
-            "Ashcon" => ApiType::Ashcon,
-            "PlayerDb" => ApiType::PlayerDb,
-            _ => panic!("Unable to parse MINECRAFT_API_TYPE environment variable"),
-        });
+    logging::init(&config);
+    info!("Reading config...");
+    if dotenv.is_err() {
+        info!("Cannot find a .env file, will attempt to use environment variables");
     }
 
-    if let Ok(rate_limit_strategy) = env::var("RATE_LIMIT_STRATEGY") {
-        config = config.rate_limit_strategy(match rate_limit_strategy.as_str() {
-            "Delay" => RateLimitStrategy::Delay,
-            "Error" => RateLimitStrategy::Error,
-            _ => panic!("Unable to parse RATE_LIMIT_STRATEGY environment variable"),
-        });
-    }
+    let keys = KeyTracker::new(&config.api_keys);
+    let client =
+        surf::Client::try_from(surf::Config::new().set_timeout(Some(Duration::from_secs(15))))?
+            .with(keys.clone());
 
-    if let Ok(minecraft_cache_ttl) = env::var("MINECRAFT_CACHE_TTL") {
-        match minecraft_cache_ttl.parse::<u64>() {
-            Ok(v) => {
-                config = config.minecraft_cache_ttl(Duration::from_secs(v));
-            }
-            Err(_) => panic!("Unable to parse MINECRAFT_CACHE_TTL environment variable"),
-        };
+    let clients = api::connect(&client, &config, &keys).await;
+    if clients.is_empty() {
+        return Err("No valid API keys were provided".into());
     }
 
-    for endpoint in HYPIXEL_ENDPOINTS {
-        if let Ok(value) = env::var(format!("HYPIXEL_CACHE_TTL.{}", endpoint.0)) {
-            match value.parse::<u64>() {
-                Ok(ttl) => {
-                    config = config.add_hypixel_cache_ttl(endpoint.1, Duration::from_secs(ttl));
-                }
-                Err(_) => {
-                    panic!(
-                        "Unable to parse HYPIXEL_CACHE_TTL.{} environment variable",
-                        endpoint.0
-                    )
-                }
-            }
+    let cache: Box<dyn CacheBackend> = match config.cache_backend {
+        CacheBackendType::Redis => {
+            Box::new(RedisCache::new(config.redis_url.as_deref().unwrap()).await?)
         }
-    }
-
+        CacheBackendType::Memory => Box::new(match &config.cache_dir {
+            Some(cache_dir) => MemoryCache::persistent(cache_dir).await?,
+            None => MemoryCache::new(),
+        }),
+    };
+
+    let sales = match &config.sales_db {
+        Some(sales_db) => Some(Sales::open(sales_db)?),
+        None => None,
+    };
+    let bazaar_history = match &config.bazaar_db {
+        Some(bazaar_db) => Some(BazaarHistory::open(bazaar_db)?),
+        None => None,
+    };
+
+    let (base_url, port) = (config.base_url.clone(), config.port);
     let web_data = Data::new(WebData {
-        api: Mutex::new(RsPixel::from_config(api_key, config.into()).await?),
+        api: ApiPool::new(
+            clients,
+            keys,
+            cache,
+            config.cache_ttls(),
+            config.rate_limit_strategy,
+        ),
+        minecraft: Resolver::new(client.clone(), &config),
+        auction_house: AuctionHouse::new(config.cache_dir.as_deref()),
+        sales,
+        bazaar_history,
+        readiness: Readiness::new(Duration::from_secs(config.ready_check_interval)),
+        rate_limits: ArcSwap::from_pointee(RateLimits::new(&config)),
+        config: ArcSwap::from_pointee(config),
+        config_file,
     });
-
-    let mut governor_conf = GovernorConfigBuilder::default();
-    let mut use_governor = false;
-    if let Ok(value) = env::var("SERVER.PERIOD") {
-        match value.parse::<u64>() {
-            Ok(period) => {
-                if period == 0 {
-                    panic!("Unable to parse SERVER.PERIOD environment variable");
-                } else {
-                    governor_conf.per_millisecond(period);
-                    use_governor = true;
-                }
-            }
-            Err(_) => {
-                panic!("Unable to parse SERVER.PERIOD environment variable");
-            }
-        }
+    #[cfg(unix)]
+    actix_web::rt::spawn(reload::reload_on_hangup(web_data.clone()));
+    let config = web_data.config.load_full();
+    if let Some(interval) = config.auction_snapshot_interval {
+        actix_web::rt::spawn(update_auctions(
+            web_data.clone(),
+            Duration::from_secs(interval),
+        ));
     }
-    if let Ok(value) = env::var("SERVER.BURST") {
-        match value.parse::<u32>() {
-            Ok(burst) => {
-                if burst == 0 {
-                    panic!("Unable to parse SERVER.PERIOD environment variable");
-                } else {
-                    governor_conf.burst_size(burst);
-                    use_governor = true;
-                }
-            }
-            Err(_) => {
-                panic!("Unable to parse SERVER.BURST environment variable");
-            }
-        }
+    if web_data.sales.is_some() {
+        actix_web::rt::spawn(record_sales(
+            web_data.clone(),
+            Duration::from_secs(config.sales_poll_interval),
+        ));
+    }
+    if web_data.bazaar_history.is_some() {
+        actix_web::rt::spawn(record_bazaar(
+            web_data.clone(),
+            Duration::from_secs(config.bazaar_sample_interval),
+        ));
     }
-    let governor = governor_conf
-        .use_headers()
-        .permissive(!use_governor)
-        .key_extractor(CachingKeyExtractor)
-        .finish()
-        .unwrap();
 
-    println!("Starting server...");
-    HttpServer::new(move || {
-        let mut app = App::new()
-            .wrap(Governor::new(&governor))
-            .app_data(web_data.clone())
-            .default_service(web::to(default));
+    info!("Starting server...");
+    HttpServer::new(move || app(web_data.clone()))
+        .bind((base_url, port))?
+        .run()
+        .await?;
 
-        for endpoint in SERVER_ENDPOINTS {
-            if let Ok(value) = env::var(format!("SERVER.ENDPOINT.{endpoint}")) {
-                match value.parse::<bool>() {
-                    Ok(enable) => {
-                        if enable {
-                            app = app.configure(|conf| add_endpoint(conf, endpoint));
-                        }
+    Ok(())
+}
+
+fn app(
+    web_data: Data<WebData>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let mut app = App::new()
+        .wrap(ReloadableGovernor::new(web_data.clone()))
+        .wrap_fn(|req, srv| {
+            // Every endpoint is registered so that they can be toggled by reloading the config
+            let access = auth::check(&req);
+            let call = match access {
+                Access::Allowed => Ok(srv.call(req)),
+                access => Err((req, access)),
+            };
+            async move {
+                match call {
+                    Ok(fut) => Ok(fut.await?.map_into_boxed_body()),
+                    Err((req, Access::Denied(err))) => {
+                        Ok(req.into_response(err.error_response().map_into_boxed_body()))
                     }
-                    Err(_) => {
-                        panic!("Unable to parse SERVER.ENDPOINT.{endpoint} environment variable")
+                    Err((req, _)) => {
+                        let res = default().await.respond_to(req.request());
+                        Ok(req.into_response(res.map_into_boxed_body()))
                     }
                 }
             }
-        }
-
-        app
-    })
-    .bind((base_url, port))?
-    .run()
-    .await?;
-
-    Ok(())
-}
-
-#[derive(Clone)]
-struct CachingKeyExtractor;
-impl KeyExtractor for CachingKeyExtractor {
-    type Key = String;
-    type KeyExtractionError = SimpleKeyExtractionError<&'static str>;
-
-    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
-        if req
-            .app_data::<Data<WebData>>()
-            .unwrap()
-            .api
-            .lock()
-            .unwrap()
-            .is_cached(
-                &req.path()[1..],
-                web::Query::<HashMap<String, String>>::from_query(req.query_string())
-                    .unwrap()
-                    .into_inner(),
-            )
-        {
-            return Ok(String::new());
-        }
-
-        req.peer_addr()
-            .map(|socket| socket.ip().to_string())
-            .ok_or_else(|| {
-                SimpleKeyExtractionError::new("Could not extract peer IP address from request")
-            })
+        })
+        .wrap_fn(|req, srv| {
+            let start = Instant::now();
+            let log = AccessLog::new(&req);
+            let fut = log.run(srv.call(req));
+            async move {
+                let res = fut.await?;
+                record_request(&res, start);
+                Ok(res)
+            }
+        })
+        .app_data(web_data)
+        .service(health::health)
+        .service(health::ready)
+        .default_service(web::to(default));
+
+    for (endpoint, _) in SERVER_ENDPOINTS {
+        app = app.configure(|conf| add_endpoint(conf, endpoint));
     }
 
-    fn whitelisted_keys(&self) -> Vec<Self::Key> {
-        vec![String::new()]
-    }
+    app
 }
@@ -0,0 +1,181 @@
+use crate::structs::WebData;
+use actix_web::{body::MessageBody, dev::ServiceResponse, get, web::Data, HttpResponse, Responder};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::time::Instant;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests by route and status",
+        &["route", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latency by route",
+        &["route"]
+    )
+    .unwrap();
+    static ref RATE_LIMITED_REQUESTS: IntCounter = register_int_counter!(
+        "rate_limited_requests_total",
+        "Number of requests rejected by the rate limiter"
+    )
+    .unwrap();
+    static ref CACHE_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "hypixel_cache_requests_total",
+        "Number of Hypixel API cache lookups by endpoint and result",
+        &["endpoint", "result"]
+    )
+    .unwrap();
+    static ref UPSTREAM_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "upstream_request_duration_seconds",
+        "Upstream request latency by upstream and endpoint",
+        &["upstream", "endpoint"]
+    )
+    .unwrap();
+    static ref UPSTREAM_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "upstream_errors_total",
+        "Number of upstream errors by upstream and error code",
+        &["upstream", "code"]
+    )
+    .unwrap();
+    static ref MINECRAFT_CIRCUIT_OPEN: IntGaugeVec = register_int_gauge_vec!(
+        "minecraft_api_circuit_open",
+        "Whether each Minecraft API is skipped after failing (1) or used (0)",
+        &["api"]
+    )
+    .unwrap();
+    static ref KEY_REMAINING: IntGaugeVec = register_int_gauge_vec!(
+        "hypixel_key_remaining",
+        "Remaining quota of each Hypixel API key",
+        &["key"]
+    )
+    .unwrap();
+}
+
+pub fn record_request<B: MessageBody>(res: &ServiceResponse<B>, start: Instant) {
+    // Unmatched paths share a label to keep the number of series bounded
+    let route = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let status = res.status();
+
+    if status == 429 && res.headers().contains_key("x-ratelimit-after") {
+        RATE_LIMITED_REQUESTS.inc();
+    }
+    HTTP_REQUESTS
+        .with_label_values(&[&route, status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&route])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+pub fn record_circuit(api: &str, open: bool) {
+    MINECRAFT_CIRCUIT_OPEN
+        .with_label_values(&[api])
+        .set(open as i64);
+}
+
+pub fn record_cache(endpoint: &str, hit: bool) {
+    CACHE_REQUESTS
+        .with_label_values(&[endpoint, if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+pub fn record_upstream(upstream: &str, endpoint: &str, start: Instant) {
+    UPSTREAM_REQUEST_DURATION
+        .with_label_values(&[upstream, endpoint])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+pub fn record_upstream_error(upstream: &str, code: &str) {
+    UPSTREAM_ERRORS.with_label_values(&[upstream, code]).inc();
+}
+
+#[get("/metrics")]
+pub async fn metrics(web_data: Data<WebData>) -> impl Responder {
+    for key in web_data.api.key_health() {
+        KEY_REMAINING
+            .with_label_values(&[&key.key])
+            .set(if key.valid {
+                key.remaining.or(key.limit).unwrap_or_default()
+            } else {
+                0
+            });
+    }
+
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(buffer),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use serde_json::json;
+
+    fn get(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn requests_upstreams_and_circuits_are_exposed() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::ok(json!({"success": true, "games": {}, "playerCount": 0})),
+        );
+        mock.respond(
+            "api.mojang.com/users/profiles/minecraft/Notch",
+            MockResponse::status(500, json!({"error": "Internal Server Error"})),
+        );
+        mock.respond(
+            "api.ashcon.app/mojang/v2/user/Notch",
+            MockResponse::ok(json!({
+                "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+                "username": "Notch"
+            })),
+        );
+        let web_data = mock
+            .web_data(config(
+                "MINECRAFT_API_TYPE = \"Mojang,Ashcon\"\n\
+                MINECRAFT_BREAKER_THRESHOLD = 1\n\
+                [SERVER.ENDPOINT]\nCOUNTS = true\nMOJANG_UUID = true\nMETRICS = true",
+            ))
+            .await;
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(&app, get("/counts").to_request()).await;
+        assert!(res.status().is_success());
+        let res = call_service(&app, get("/mojang/uuid?username=Notch").to_request()).await;
+        assert!(res.status().is_success());
+
+        let res = call_service(&app, get("/metrics").to_request()).await;
+        assert!(res.status().is_success());
+        let body = String::from_utf8(read_body(res).await.to_vec()).unwrap();
+        for series in [
+            "http_requests_total{route=\"/counts\",status=\"200\"}",
+            "http_request_duration_seconds_count{route=\"/mojang/uuid\"}",
+            "upstream_request_duration_seconds_count{endpoint=\"counts\",upstream=\"hypixel\"}",
+            "upstream_request_duration_seconds_count{endpoint=\"username_to_uuid\",upstream=\"ashcon\"}",
+            "upstream_errors_total{code=\"upstream_error\",upstream=\"mojang\"}",
+            "minecraft_api_circuit_open{api=\"mojang\"}",
+            "hypixel_key_remaining{key=",
+        ] {
+            assert!(body.contains(series), "{series} is missing from\n{body}");
+        }
+    }
+}
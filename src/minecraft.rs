@@ -0,0 +1,349 @@
+use crate::{
+    config::{Config, MinecraftApiType},
+    error::{Upstream, WebError},
+    logging::upstream,
+    metrics::record_circuit,
+    utils::parse_uuid,
+};
+use actix_web::http::StatusCode;
+use moka::future::Cache;
+use rs_pixel::util::error::Error;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use surf::Url;
+use tracing::{info, warn};
+
+/// Current username and undashed uuid of a player
+#[derive(Serialize, Clone)]
+pub struct Profile {
+    pub uuid: String,
+    pub username: String,
+}
+
+/// Circuit breaker of a Minecraft API
+#[derive(Default)]
+struct Breaker {
+    /// Consecutive failures
+    failures: u32,
+    /// Until when the API is skipped, it is tried again by a single request afterwards
+    open_until: Option<Instant>,
+}
+
+/// Resolves usernames and uuids, trying each Minecraft API in order until one of them responds.
+/// APIs that keep failing are skipped until they are tried again after a cooldown.
+pub struct Resolver {
+    client: surf::Client,
+    /// `MINECRAFT_API_TYPE`
+    apis: Vec<MinecraftApiType>,
+    breakers: Mutex<HashMap<MinecraftApiType, Breaker>>,
+    breaker_threshold: u32,
+    breaker_cooldown: Duration,
+    /// Uuids by lowercase username, kept for `MINECRAFT_CACHE_TTL`
+    uuids: Option<Cache<String, String>>,
+    /// Usernames by uuid, kept for `MINECRAFT_CACHE_TTL`
+    usernames: Option<Cache<String, String>>,
+}
+
+impl Resolver {
+    pub fn new(client: surf::Client, config: &Config) -> Resolver {
+        let cache_ttl = config.minecraft_cache_ttl.map(Duration::from_secs);
+        for api in MinecraftApiType::ALL {
+            record_circuit(Upstream::from(&api.api_type()).as_str(), false);
+        }
+
+        Resolver {
+            client,
+            apis: config.minecraft_api_type.clone(),
+            breakers: Mutex::new(HashMap::new()),
+            breaker_threshold: config.minecraft_breaker_threshold,
+            breaker_cooldown: Duration::from_secs(config.minecraft_breaker_cooldown),
+            uuids: cache_ttl.map(|ttl| Cache::builder().time_to_live(ttl).build()),
+            usernames: cache_ttl.map(|ttl| Cache::builder().time_to_live(ttl).build()),
+        }
+    }
+
+    /// The default fallback order
+    pub fn apis(&self) -> &[MinecraftApiType] {
+        &self.apis
+    }
+
+    pub fn cached_uuid(&self, username: &str) -> Option<String> {
+        self.uuids.as_ref()?.get(&username.to_lowercase())
+    }
+
+    pub async fn username_to_uuid(
+        &self,
+        username: &str,
+        apis: &[MinecraftApiType],
+    ) -> Result<Profile, WebError> {
+        if let Some(uuid) = self.cached_uuid(username) {
+            if let Some(username) = self.cached_username(&uuid) {
+                return Ok(Profile { uuid, username });
+            }
+        }
+        self.resolve(username, false, apis).await
+    }
+
+    /// `uuid` must be undashed
+    pub async fn uuid_to_username(
+        &self,
+        uuid: &str,
+        apis: &[MinecraftApiType],
+    ) -> Result<Profile, WebError> {
+        if let Some(username) = self.cached_username(uuid) {
+            return Ok(Profile {
+                uuid: uuid.to_string(),
+                username,
+            });
+        }
+        self.resolve(uuid, true, apis).await
+    }
+
+    /// Resolves a username without the cache to check that the APIs are reachable
+    pub async fn probe(&self, username: &str) -> Result<(), WebError> {
+        self.resolve(username, false, &self.apis).await.map(|_| ())
+    }
+
+    pub fn cached_username(&self, uuid: &str) -> Option<String> {
+        self.usernames.as_ref()?.get(uuid)
+    }
+
+    async fn resolve(
+        &self,
+        player: &str,
+        is_uuid: bool,
+        apis: &[MinecraftApiType],
+    ) -> Result<Profile, WebError> {
+        let endpoint = if is_uuid {
+            "uuid_to_username"
+        } else {
+            "username_to_uuid"
+        };
+
+        let mut last_err = None;
+        for api in apis {
+            if !self.is_available(*api) {
+                continue;
+            }
+
+            let upstream_name = Upstream::from(&api.api_type()).as_str();
+            let res = upstream(upstream_name, endpoint, self.request(*api, player, is_uuid)).await;
+            match res {
+                Ok(profile) => {
+                    self.record_success(*api);
+                    self.cache(&profile).await;
+                    return Ok(profile);
+                }
+                Err(err) => {
+                    let err = WebError::minecraft(err, &api.api_type());
+                    // Unknown players and invalid requests fail the same way with every API
+                    if err.status.is_client_error() && err.status != StatusCode::TOO_MANY_REQUESTS {
+                        self.record_success(*api);
+                        return Err(err);
+                    }
+                    self.record_failure(*api);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            WebError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "minecraft_unavailable",
+                "No Minecraft API is available",
+            )
+        }))
+    }
+
+    /// Whether a request can be sent to an API, only one request is let through once its cooldown ends
+    fn is_available(&self, api: MinecraftApiType) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(api).or_default();
+        match breaker.open_until {
+            Some(open_until) if Instant::now() < open_until => false,
+            Some(_) => {
+                // Other requests keep skipping the API while this one checks whether it recovered
+                breaker.open_until = Some(Instant::now() + self.breaker_cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record_success(&self, api: MinecraftApiType) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(api).or_default();
+        if breaker.open_until.is_some() {
+            info!("{api:?} API recovered");
+            record_circuit(Upstream::from(&api.api_type()).as_str(), false);
+        }
+        *breaker = Breaker::default();
+    }
+
+    fn record_failure(&self, api: MinecraftApiType) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(api).or_default();
+        breaker.failures += 1;
+        if breaker.failures >= self.breaker_threshold {
+            if breaker.open_until.is_none() {
+                warn!(
+                    "Skipping the {api:?} API for {}s after {} failures",
+                    self.breaker_cooldown.as_secs(),
+                    breaker.failures
+                );
+                record_circuit(Upstream::from(&api.api_type()).as_str(), true);
+            }
+            breaker.open_until = Some(Instant::now() + self.breaker_cooldown);
+        }
+    }
+
+    async fn request(
+        &self,
+        api: MinecraftApiType,
+        player: &str,
+        is_uuid: bool,
+    ) -> Result<Profile, Error> {
+        let base = match (api, is_uuid) {
+            (MinecraftApiType::Mojang, false) => "https://api.mojang.com/users/profiles/minecraft",
+            (MinecraftApiType::Mojang, true) => {
+                "https://sessionserver.mojang.com/session/minecraft/profile"
+            }
+            (MinecraftApiType::Ashcon, _) => "https://api.ashcon.app/mojang/v2/user",
+            (MinecraftApiType::PlayerDb, _) => "https://playerdb.co/api/player/minecraft",
+        };
+        // Usernames are only checked for their length, so they are percent-encoded into the path
+        let mut url = Url::parse(base).unwrap();
+        url.path_segments_mut().unwrap().push(player);
+
+        let mut res = self.client.get(url).await?;
+        if res.status() != 200 {
+            let body = res.body_string().await.unwrap_or_default();
+            return Err(Error::Status(res.status().into(), body));
+        }
+
+        let json = res.body_json::<Value>().await?;
+        let (uuid, username) = match api {
+            MinecraftApiType::Mojang => (&json["id"], &json["name"]),
+            MinecraftApiType::Ashcon => (&json["uuid"], &json["username"]),
+            MinecraftApiType::PlayerDb => (
+                &json["data"]["player"]["id"],
+                &json["data"]["player"]["username"],
+            ),
+        };
+        match (uuid.as_str().and_then(parse_uuid), username.as_str()) {
+            (Some(uuid), Some(username)) => Ok(Profile {
+                uuid,
+                username: username.to_string(),
+            }),
+            _ => Err(Error::Unknown("Unexpected response".to_string())),
+        }
+    }
+
+    async fn cache(&self, profile: &Profile) {
+        if let (Some(uuids), Some(usernames)) = (&self.uuids, &self.usernames) {
+            uuids
+                .insert(profile.username.to_lowercase(), profile.uuid.clone())
+                .await;
+            usernames
+                .insert(profile.uuid.clone(), profile.username.clone())
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use futures::future::join;
+    use serde_json::json;
+
+    const MOJANG: &str = "api.mojang.com/users/profiles/minecraft/Notch";
+    const ASHCON: &str = "api.ashcon.app/mojang/v2/user/Notch";
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        mock.respond(
+            MOJANG,
+            MockResponse::status(500, json!({"error": "Internal Server Error"})),
+        );
+        mock.respond(
+            ASHCON,
+            MockResponse::ok(json!({
+                "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+                "username": "Notch"
+            })),
+        );
+        mock
+    }
+
+    fn resolver(mock: &MockUpstream) -> Resolver {
+        Resolver::new(
+            mock.client(),
+            &config(
+                "MINECRAFT_API_TYPE = \"Mojang,Ashcon\"\n\
+                MINECRAFT_BREAKER_THRESHOLD = 2\n\
+                MINECRAFT_BREAKER_COOLDOWN = 1",
+            ),
+        )
+    }
+
+    async fn resolve(resolver: &Resolver) -> Result<Profile, WebError> {
+        resolver.username_to_uuid("Notch", resolver.apis()).await
+    }
+
+    #[actix_web::test]
+    async fn failing_apis_fall_back_to_the_next_one() {
+        let mock = mock();
+        let resolver = resolver(&mock);
+
+        let profile = resolve(&resolver).await.unwrap();
+        assert_eq!(profile.uuid, "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(mock.hits(MOJANG), 1);
+        assert_eq!(mock.hits(ASHCON), 1);
+    }
+
+    #[actix_web::test]
+    async fn failing_apis_are_skipped_after_the_threshold() {
+        let mock = mock();
+        let resolver = resolver(&mock);
+
+        for _ in 0..4 {
+            resolve(&resolver).await.unwrap();
+        }
+        assert_eq!(mock.hits(MOJANG), 2);
+        assert_eq!(mock.hits(ASHCON), 4);
+    }
+
+    #[actix_web::test]
+    async fn one_request_checks_the_api_after_the_cooldown() {
+        let mock = mock();
+        let resolver = resolver(&mock);
+        for _ in 0..2 {
+            resolve(&resolver).await.unwrap();
+        }
+
+        mock.respond(
+            MOJANG,
+            MockResponse::ok(json!({"id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch"}))
+                .delay(Duration::from_millis(100)),
+        );
+        actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+        let (first, second) = join(resolve(&resolver), resolve(&resolver)).await;
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(mock.hits(MOJANG), 3);
+        assert_eq!(mock.hits(ASHCON), 3);
+
+        // The API recovered so it is tried first again
+        resolve(&resolver).await.unwrap();
+        assert_eq!(mock.hits(MOJANG), 4);
+        assert_eq!(mock.hits(ASHCON), 3);
+    }
+}
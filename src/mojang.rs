@@ -0,0 +1,206 @@
+use crate::{
+    config::MinecraftApiType,
+    error::WebError,
+    minecraft::Profile,
+    rate_limit::charge,
+    structs::{BatchResult, MojangQuery, WebData},
+    utils::{bad_request, is_valid_username, parse_uuid},
+};
+use actix_web::{
+    get,
+    http::StatusCode,
+    post,
+    web::{Data, Json, Query},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use futures::{stream, StreamExt};
+use serde_json::{json, Value};
+
+#[get("/mojang/uuid")]
+pub async fn mojang_uuid(web_data: Data<WebData>, query: Query<MojangQuery>) -> impl Responder {
+    let apis = match minecraft_apis(&web_data, query.apis.as_deref()) {
+        Ok(apis) => apis,
+        Err(err) => return err.error_response(),
+    };
+    let Some(username) = &query.username else {
+        return bad_request("Missing one or more fields [username]");
+    };
+    if !is_valid_username(username) {
+        return bad_request("Invalid username provided");
+    }
+
+    match web_data.minecraft.username_to_uuid(username, &apis).await {
+        Ok(profile) => HttpResponse::Ok().json(profile_json(profile)),
+        Err(err) => err.error_response(),
+    }
+}
+
+#[get("/mojang/username")]
+pub async fn mojang_username(web_data: Data<WebData>, query: Query<MojangQuery>) -> impl Responder {
+    let apis = match minecraft_apis(&web_data, query.apis.as_deref()) {
+        Ok(apis) => apis,
+        Err(err) => return err.error_response(),
+    };
+    let Some(uuid) = &query.uuid else {
+        return bad_request("Missing one or more fields [uuid]");
+    };
+    let Some(uuid) = parse_uuid(uuid) else {
+        return bad_request("Invalid uuid provided");
+    };
+
+    match web_data.minecraft.uuid_to_username(&uuid, &apis).await {
+        Ok(profile) => HttpResponse::Ok().json(profile_json(profile)),
+        Err(err) => err.error_response(),
+    }
+}
+
+/// Resolves a JSON list of usernames, `BATCH_CONCURRENCY` at a time, and responds with their results in order
+#[post("/mojang/uuids")]
+pub async fn mojang_uuids(
+    web_data: Data<WebData>,
+    req: HttpRequest,
+    query: Query<MojangQuery>,
+    usernames: Json<Vec<String>>,
+) -> impl Responder {
+    let apis = match minecraft_apis(&web_data, query.apis.as_deref()) {
+        Ok(apis) => apis,
+        Err(err) => return err.error_response(),
+    };
+    let config = web_data.config.load();
+    if usernames.len() > config.batch_max_size {
+        return WebError::bad_request(format!(
+            "At most {} usernames can be resolved at once",
+            config.batch_max_size
+        ))
+        .error_response();
+    }
+
+    // The request took one request from the quota of the client, every other username that isn't cached takes one more
+    let uncached = usernames
+        .iter()
+        .filter(|username| {
+            is_valid_username(username)
+                && web_data
+                    .minecraft
+                    .cached_uuid(username)
+                    .and_then(|uuid| web_data.minecraft.cached_username(&uuid))
+                    .is_none()
+        })
+        .count();
+    if let Err(err) = charge(&web_data, &req, uncached.saturating_sub(1)) {
+        return err.error_response();
+    }
+
+    let results = stream::iter(usernames.into_inner())
+        .map(|username| {
+            let (web_data, apis) = (&web_data, &apis);
+            async move {
+                if !is_valid_username(&username) {
+                    return Err(WebError::bad_request("Invalid username provided"));
+                }
+                web_data.minecraft.username_to_uuid(&username, apis).await
+            }
+        })
+        .buffered(config.batch_concurrency)
+        .map(|res| match res {
+            Ok(profile) => BatchResult {
+                status: StatusCode::OK.as_u16(),
+                response: profile_json(profile),
+            },
+            Err(err) => BatchResult {
+                status: err.status.as_u16(),
+                response: json!(err),
+            },
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    HttpResponse::Ok().json(json!({ "success": true, "results": results }))
+}
+
+/// Parses the `apis` fallback order, defaulting to `MINECRAFT_API_TYPE`
+fn minecraft_apis(
+    web_data: &WebData,
+    apis: Option<&str>,
+) -> Result<Vec<MinecraftApiType>, WebError> {
+    let Some(apis) = apis else {
+        return Ok(web_data.minecraft.apis().to_vec());
+    };
+
+    apis.split(',')
+        .map(|api| {
+            api.trim().parse::<MinecraftApiType>().map_err(|_| {
+                WebError::bad_request(format!(
+                    "Invalid apis provided, expected a list of Mojang, Ashcon, or PlayerDb but found `{api}`"
+                ))
+            })
+        })
+        .collect()
+}
+
+fn profile_json(profile: Profile) -> Value {
+    json!({
+        "success": true,
+        "uuid": profile.uuid,
+        "username": profile.username,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+    };
+    use serde_json::json;
+
+    fn uuids(count: usize) -> TestRequest {
+        TestRequest::post()
+            .uri("/mojang/uuids")
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+            .set_json(
+                (0..count)
+                    .map(|idx| format!("player{idx}"))
+                    .collect::<Vec<_>>(),
+            )
+    }
+
+    #[actix_web::test]
+    async fn uncached_usernames_take_from_the_quota() {
+        let mock = MockUpstream::start();
+        for idx in 0..6 {
+            mock.respond(
+                &format!("api.mojang.com/users/profiles/minecraft/player{idx}"),
+                MockResponse::ok(
+                    json!({"id": format!("{idx:032x}"), "name": format!("player{idx}")}),
+                ),
+            );
+        }
+        let settings = "MINECRAFT_API_TYPE = \"Mojang\"\nMINECRAFT_CACHE_TTL = 60\n\
+            [SERVER]\nPERIOD = 60000\nBURST = 5\n[SERVER.ENDPOINT]\nMOJANG_UUIDS = true";
+        let app = init_service(crate::app(mock.web_data(config(settings)).await)).await;
+        let hits = || {
+            (0..6)
+                .map(|idx| {
+                    mock.hits(&format!(
+                        "api.mojang.com/users/profiles/minecraft/player{idx}"
+                    ))
+                })
+                .sum::<usize>()
+        };
+
+        let res = call_service(&app, uuids(6).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(hits(), 0);
+
+        let res = call_service(&app, uuids(3).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(hits(), 3);
+
+        // Every username is cached, so only the request itself takes from the quota
+        let res = call_service(&app, uuids(3).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(hits(), 3);
+    }
+}
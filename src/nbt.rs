@@ -0,0 +1,208 @@
+use crate::skyblock::strip_formatting;
+use rs_pixel::util::utils::parse_nbt;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Gzipped NBT encoded as base64 starts with the gzip magic bytes
+const GZIP_BASE64_PREFIX: &str = "H4sI";
+
+/// A SkyBlock item decoded from NBT
+#[derive(Serialize)]
+pub struct Item {
+    /// SkyBlock id, the Minecraft id for vanilla items
+    pub id: Option<String>,
+    pub count: u64,
+    pub name: Option<String>,
+    pub lore: Vec<String>,
+    pub enchantments: Map<String, Value>,
+    pub reforge: Option<String>,
+    /// Dungeon stars
+    pub stars: u64,
+    /// Including fuming potato books
+    pub hot_potato_books: u64,
+    pub dungeon_item: bool,
+    pub recombobulated: bool,
+}
+
+impl Item {
+    /// Structures an item of a decoded NBT inventory, `None` for empty slots
+    pub fn from_nbt(item: &Value) -> Option<Item> {
+        let tag = item.get("tag")?;
+        let extra_attributes = &tag["ExtraAttributes"];
+        let number = |name: &str| extra_attributes[name].as_u64();
+
+        Some(Item {
+            id: extra_attributes["id"]
+                .as_str()
+                .map(str::to_string)
+                // Minecraft ids are numeric in the 1.8 NBT SkyBlock uses
+                .or_else(|| match &item["id"] {
+                    Value::String(id) => Some(id.clone()),
+                    Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                }),
+            count: item["Count"].as_u64().unwrap_or(1),
+            name: tag["display"]["Name"].as_str().map(strip_formatting),
+            lore: tag["display"]["Lore"]
+                .as_array()
+                .map(|lore| {
+                    lore.iter()
+                        .filter_map(Value::as_str)
+                        .map(strip_formatting)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            enchantments: extra_attributes["enchantments"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default(),
+            reforge: extra_attributes["modifier"].as_str().map(str::to_string),
+            // Older items store their stars as `dungeon_item_level`
+            stars: number("upgrade_level")
+                .or_else(|| number("dungeon_item_level"))
+                .unwrap_or(0),
+            hot_potato_books: number("hot_potato_count").unwrap_or(0),
+            dungeon_item: number("dungeon_item") == Some(1),
+            recombobulated: number("rarity_upgrades").is_some_and(|upgrades| upgrades > 0),
+        })
+    }
+}
+
+/// Items of a gzipped and base64 encoded NBT inventory, `None` if it can't be decoded
+pub fn decode(data: &str) -> Option<Vec<Value>> {
+    parse_nbt(data).and_then(|items| items.as_array().cloned())
+}
+
+/// Items of an NBT inventory with empty slots left out
+pub fn inventory_items(data: &str) -> Vec<Value> {
+    decode(data)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|item| item.get("tag").is_some())
+        .collect()
+}
+
+/// Structured items of an NBT inventory, empty slots are null so that items keep their slot
+pub fn decode_items(data: &str) -> Option<Vec<Value>> {
+    Some(
+        decode(data)?
+            .iter()
+            .map(|item| json!(Item::from_nbt(item)))
+            .collect(),
+    )
+}
+
+/// Replaces the `data` of every NBT inventory (like `{"type": 0, "data": "H4sI..."}`)
+/// in the member data of a profile with its decoded `items`
+pub fn decode_inventories(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let items = object
+                .get("data")
+                .and_then(Value::as_str)
+                .filter(|data| data.starts_with(GZIP_BASE64_PREFIX))
+                .and_then(decode_items);
+            match items {
+                Some(items) => {
+                    object.remove("data");
+                    object.insert("items".to_string(), json!(items));
+                }
+                None => object.values_mut().for_each(decode_inventories),
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(decode_inventories),
+        _ => {}
+    }
+}
+
+/// Replaces the `item_bytes` of every auction with its decoded `item`
+pub fn decode_auction_items(auctions: &mut Value) {
+    for auction in auctions.as_array_mut().into_iter().flatten() {
+        let item = auction["item_bytes"]
+            .as_str()
+            .and_then(decode)
+            .and_then(|items| items.first().and_then(Item::from_nbt));
+        if let (Some(item), Some(auction)) = (item, auction.as_object_mut()) {
+            auction.remove("item_bytes");
+            auction.insert("item".to_string(), json!(item));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Profiles response of a player with items in every valued inventory
+    const PROFILES: &str = include_str!("../tests/fixtures/profile.json");
+
+    fn member() -> Value {
+        let profiles = serde_json::from_str::<Value>(PROFILES).unwrap();
+        profiles["profiles"][0]["members"]["069a79f444e94726a5befca90e38aaf5"].clone()
+    }
+
+    fn inventory() -> String {
+        member()["inventory"]["inv_contents"]["data"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn items_keep_their_slot() {
+        let items = decode_items(&inventory()).unwrap();
+        assert_eq!(items.len(), 36);
+        assert_eq!(items[0]["id"], "ASPECT_OF_THE_END");
+        assert_eq!(items[0]["name"], "Aspect of the End");
+        assert_eq!(items[0]["lore"][0], "Damage: +100");
+        assert_eq!(items[0]["enchantments"]["sharpness"], 5);
+        assert_eq!(items[0]["reforge"], "heroic");
+        assert_eq!(items[0]["hot_potato_books"], 10);
+        assert_eq!(items[1]["count"], 5);
+        assert!(items[2].is_null());
+        // Vanilla items fall back to their Minecraft id
+        assert_eq!(items[4]["id"], "345");
+        assert_eq!(items[4]["count"], 1);
+    }
+
+    #[test]
+    fn empty_slots_are_left_out_of_inventory_items() {
+        let items = inventory_items(&inventory());
+        assert_eq!(items.len(), 5);
+        assert!(items.iter().all(|item| item.get("tag").is_some()));
+    }
+
+    #[test]
+    fn inventories_of_a_profile_are_decoded_in_place() {
+        let mut member = member();
+        decode_inventories(&mut member);
+
+        let armor = &member["inventory"]["inv_armor"];
+        assert!(armor.get("data").is_none());
+        assert_eq!(armor["type"], 0);
+        assert_eq!(armor["items"][3]["id"], "SUPERIOR_DRAGON_HELMET");
+        assert_eq!(armor["items"][3]["recombobulated"], true);
+        let accessories = &member["inventory"]["bag_contents"]["talisman_bag"]["items"];
+        assert_eq!(accessories[0]["id"], "HEGEMONY_ARTIFACT");
+    }
+
+    #[test]
+    fn undecodable_data_is_rejected_without_panicking() {
+        // Not base64, base64 of something that isn't gzip, and truncated gzip
+        let truncated = &inventory()[..40];
+        for data in ["not base64!", "aGVsbG8gd29ybGQ=", "H4sIAAAAAAAA", truncated] {
+            assert!(decode(data).is_none(), "{data}");
+            assert!(decode_items(data).is_none(), "{data}");
+            assert!(inventory_items(data).is_empty(), "{data}");
+        }
+
+        let mut member = json!({"inv_contents": {"type": 0, "data": "H4sIAAAAAAAA"}});
+        decode_inventories(&mut member);
+        assert_eq!(member["inv_contents"]["data"], "H4sIAAAAAAAA");
+
+        let mut auctions = json!([{"uuid": "a", "item_bytes": "not base64!"}]);
+        decode_auction_items(&mut auctions);
+        assert_eq!(auctions[0]["item_bytes"], "not base64!");
+        assert!(auctions[0].get("item").is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    error::WebError,
+    extractors::ResolvedPlayer,
+    nbt::inventory_items,
+    prices::{item_key, pet_key, Prices},
+    skyblock::{fetch_profile, property, strip_formatting, ProfileSelector},
+    structs::{SkyblockProfilesQuery, WebData},
+};
+use actix_web::{
+    get,
+    web::{Data, Query},
+    HttpResponse, Responder, ResponseError,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Inventories that are valued, with their current and legacy paths in the member data
+const INVENTORIES: [(&str, [&str; 2]); 5] = [
+    (
+        "inventory",
+        ["inventory.inv_contents.data", "inv_contents.data"],
+    ),
+    ("armor", ["inventory.inv_armor.data", "inv_armor.data"]),
+    (
+        "ender_chest",
+        [
+            "inventory.ender_chest_contents.data",
+            "ender_chest_contents.data",
+        ],
+    ),
+    (
+        "wardrobe",
+        ["inventory.wardrobe_contents.data", "wardrobe_contents.data"],
+    ),
+    (
+        "accessories",
+        [
+            "inventory.bag_contents.talisman_bag.data",
+            "talisman_bag.data",
+        ],
+    ),
+];
+
+#[derive(Serialize)]
+struct PricedItem {
+    id: String,
+    name: String,
+    count: u64,
+    /// Price of all of them
+    price: f64,
+}
+
+/// Items of a category that have a price, most valuable first
+#[derive(Serialize, Default)]
+struct Category {
+    total: f64,
+    items: Vec<PricedItem>,
+}
+
+impl Category {
+    fn add(&mut self, prices: &Prices, key: String, name: String, count: u64) {
+        if let Some(price) = prices.price(&key) {
+            let price = price * count as f64;
+            self.total += price;
+            self.items.push(PricedItem {
+                id: key,
+                name,
+                count,
+                price,
+            });
+        }
+    }
+
+    fn sorted(mut self) -> Category {
+        self.items.sort_by(|a, b| b.price.total_cmp(&a.price));
+        self
+    }
+}
+
+/// Value of a player's coins, items, pets, and sacks on one of their profiles
+#[get("/skyblock/networth")]
+pub async fn skyblock_networth(
+    web_data: Data<WebData>,
+    query: Query<SkyblockProfilesQuery>,
+    resolved: ResolvedPlayer,
+) -> impl Responder {
+    let selector = ProfileSelector::parse(query.profile.as_deref().unwrap_or("selected"));
+    let res = futures::try_join!(fetch_profile(&web_data, &resolved.uuid, &selector), async {
+        web_data
+            .api
+            .get_skyblock_bazaar()
+            .await
+            .map_err(WebError::hypixel)
+    },);
+    let (profile, bazaar) = match res {
+        Ok(res) => res,
+        Err(err) => return err.error_response(),
+    };
+
+    // Pulling every auction page would take too long, so items are only priced from the bazaar
+    // until a snapshot is taken
+    let auctions = web_data.auction_house.latest();
+    let no_auctions = HashMap::new();
+    let lowest_bin = auctions
+        .as_ref()
+        .map_or(&no_auctions, |snapshot| &snapshot.lowest_bin);
+    let prices = Prices::new(&bazaar, lowest_bin);
+    let mut res = networth(&profile, &resolved.uuid, &prices);
+    res["success"] = json!(true);
+    res["uuid"] = json!(resolved.uuid);
+    res["profile_id"] = profile["profile_id"].clone();
+    res["cute_name"] = profile["cute_name"].clone();
+    HttpResponse::Ok().json(res)
+}
+
+/// Networth of the member with the undashed `uuid` of a profile, as `networth`, `purse`, `bank`,
+/// and the `categories` of priced items
+pub fn networth(profile: &Value, uuid: &str, prices: &Prices) -> Value {
+    let member = &profile["members"][uuid];
+    let mut categories = Map::new();
+
+    for (name, paths) in INVENTORIES {
+        let mut category = Category::default();
+        let items = property(member, &paths)
+            .and_then(Value::as_str)
+            .map(inventory_items)
+            .unwrap_or_default();
+        for item in items {
+            let Some(key) = item_key(&item["tag"]["ExtraAttributes"]) else {
+                continue;
+            };
+            let name = item["tag"]["display"]["Name"]
+                .as_str()
+                .map(strip_formatting)
+                .unwrap_or_else(|| key.clone());
+            let count = item["Count"].as_u64().unwrap_or(1);
+            category.add(prices, key, name, count);
+        }
+        categories.insert(name.to_string(), json!(category.sorted()));
+    }
+
+    let mut pets = Category::default();
+    let profile_pets = property(member, &["pets_data.pets", "pets"])
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for pet in profile_pets {
+        if let Some(key) = pet_key(&pet) {
+            let name = pet["type"].as_str().unwrap_or_default().to_string();
+            pets.add(prices, key, name, 1);
+        }
+    }
+    categories.insert("pets".to_string(), json!(pets.sorted()));
+
+    let mut sacks = Category::default();
+    let sack_counts = property(member, &["inventory.sacks_counts", "sacks_counts"])
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    for (id, count) in sack_counts {
+        match count.as_u64() {
+            Some(count) if count > 0 => sacks.add(prices, id.clone(), id, count),
+            _ => {}
+        }
+    }
+    categories.insert("sacks".to_string(), json!(sacks.sorted()));
+
+    let purse = property(member, &["currencies.coin_purse", "coin_purse"])
+        .and_then(Value::as_f64)
+        .unwrap_or(0.0);
+    let bank = profile["banking"]["balance"].as_f64().unwrap_or(0.0);
+    let items: f64 = categories
+        .values()
+        .filter_map(|category| category["total"].as_f64())
+        .sum();
+
+    json!({
+        "networth": purse + bank + items,
+        "purse": purse,
+        "bank": bank,
+        "categories": categories,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "069a79f444e94726a5befca90e38aaf5";
+
+    fn profile() -> Value {
+        json!({
+            "members": {
+                UUID: {
+                    "currencies": {"coin_purse": 1000.0},
+                    "pets_data": {"pets": [
+                        {"type": "ENDER_DRAGON", "tier": "LEGENDARY", "exp": 0.0},
+                        {"type": "BEE", "tier": "COMMON", "exp": 0.0}
+                    ]},
+                    "inventory": {"sacks_counts": {"ENCHANTED_DIAMOND": 3, "DIRT": 0}}
+                }
+            },
+            "banking": {"balance": 500.0}
+        })
+    }
+
+    fn prices(lowest_bin: &HashMap<String, f64>) -> Prices<'_> {
+        Prices {
+            bazaar: HashMap::from([("ENCHANTED_DIAMOND".to_string(), 800.0)]),
+            lowest_bin,
+        }
+    }
+
+    #[test]
+    fn pets_are_priced_from_the_auction_snapshot() {
+        let lowest_bin = HashMap::from([("PET;ENDER_DRAGON;LEGENDARY".to_string(), 500_000_000.0)]);
+        let res = networth(&profile(), UUID, &prices(&lowest_bin));
+
+        let pets = &res["categories"]["pets"];
+        assert_eq!(pets["total"], 500_000_000.0);
+        assert_eq!(pets["items"][0]["id"], "PET;ENDER_DRAGON;LEGENDARY");
+        assert_eq!(pets["items"].as_array().unwrap().len(), 1);
+        assert_eq!(res["networth"], 1500.0 + 2400.0 + 500_000_000.0);
+    }
+
+    /// Profiles response of a player with items in every valued inventory
+    const PROFILES: &str = include_str!("../tests/fixtures/profile.json");
+
+    #[test]
+    fn inventories_are_decoded_and_priced_by_category() {
+        let profiles = serde_json::from_str::<Value>(PROFILES).unwrap();
+        let lowest_bin = HashMap::from([
+            ("ASPECT_OF_THE_END".to_string(), 300_000.0),
+            ("ENCHANTED_BOOK;ULTIMATE_WISE;5".to_string(), 1_000_000.0),
+            ("SUPERIOR_DRAGON_HELMET".to_string(), 2_000_000.0),
+            ("SUPERIOR_DRAGON_BOOTS".to_string(), 1_500_000.0),
+            ("PET;BEE;EPIC".to_string(), 250_000.0),
+            ("STRONG_DRAGON_CHESTPLATE".to_string(), 4_000_000.0),
+            ("HEGEMONY_ARTIFACT".to_string(), 90_000_000.0),
+            ("PET;ENDER_DRAGON;LEGENDARY".to_string(), 500_000_000.0),
+        ]);
+        let res = networth(&profiles["profiles"][0], UUID, &prices(&lowest_bin));
+
+        let categories = &res["categories"];
+        for (category, total) in [
+            ("inventory", 300_000.0 + 5.0 * 800.0 + 1_000_000.0),
+            ("armor", 3_500_000.0),
+            ("ender_chest", 250_000.0),
+            ("wardrobe", 4_000_000.0),
+            ("accessories", 90_000_000.0),
+            ("pets", 500_000_000.0),
+            ("sacks", 2.0 * 800.0),
+        ] {
+            assert_eq!(categories[category]["total"], total, "{category}");
+        }
+        let inventory = &categories["inventory"]["items"];
+        assert_eq!(inventory[0]["id"], "ENCHANTED_BOOK;ULTIMATE_WISE;5");
+        assert_eq!(inventory[1]["name"], "Aspect of the End");
+        assert_eq!(inventory[2]["count"], 5);
+        assert_eq!(inventory.as_array().unwrap().len(), 3);
+        assert_eq!(
+            res["networth"],
+            12_345.6
+                + 250_000.5
+                + 1_304_000.0
+                + 3_500_000.0
+                + 250_000.0
+                + 4_000_000.0
+                + 90_000_000.0
+                + 500_000_000.0
+                + 1600.0
+        );
+    }
+
+    #[test]
+    fn items_are_priced_from_the_bazaar_without_a_snapshot() {
+        let res = networth(&profile(), UUID, &prices(&HashMap::new()));
+
+        assert_eq!(res["categories"]["pets"]["total"], 0.0);
+        assert_eq!(res["categories"]["sacks"]["total"], 2400.0);
+        assert_eq!(res["networth"], 1000.0 + 500.0 + 2400.0);
+    }
+}
@@ -0,0 +1,175 @@
+use rs_pixel::response::skyblock::skyblock_bazaar_response::SkyblockBazaarResponse;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Price of one of each item by item key
+pub struct Prices<'a> {
+    /// Instant sell prices from the bazaar
+    pub bazaar: HashMap<String, f64>,
+    pub lowest_bin: &'a HashMap<String, f64>,
+}
+
+impl Prices<'_> {
+    pub fn new<'a>(
+        bazaar: &SkyblockBazaarResponse,
+        lowest_bin: &'a HashMap<String, f64>,
+    ) -> Prices<'a> {
+        Prices {
+            bazaar: bazaar
+                .products
+                .iter()
+                .map(|(id, product)| (id.clone(), product.quick_status.sell_price))
+                .collect(),
+            lowest_bin,
+        }
+    }
+
+    /// Bazaar items are priced from the bazaar and every other item from its lowest BIN
+    pub fn price(&self, key: &str) -> Option<f64> {
+        self.bazaar
+            .get(key)
+            .filter(|price| **price > 0.0)
+            .or_else(|| self.lowest_bin.get(key))
+            .copied()
+    }
+}
+
+/// Key an item is priced by, its SkyBlock id with the attributes that change its price for
+/// items that share an id: `PET;<type>;<tier>`, `ENCHANTED_BOOK;<enchantment>;<level>`
+/// for books with a single enchantment, and `RUNE;<rune>;<level>`
+pub fn item_key(extra_attributes: &Value) -> Option<String> {
+    let id = extra_attributes["id"].as_str()?;
+    let single = |attributes: &Value| {
+        let attributes = attributes.as_object()?;
+        match attributes.iter().next() {
+            Some((name, level)) if attributes.len() == 1 => {
+                Some(format!("{id};{};{level}", name.to_uppercase()))
+            }
+            _ => None,
+        }
+    };
+
+    let key = match id {
+        "PET" => extra_attributes["petInfo"]
+            .as_str()
+            .and_then(|pet_info| serde_json::from_str::<Value>(pet_info).ok())
+            .and_then(|pet_info| pet_key(&pet_info)),
+        "ENCHANTED_BOOK" => single(&extra_attributes["enchantments"]),
+        "RUNE" | "UNIQUE_RUNE" => single(&extra_attributes["runes"]),
+        _ => None,
+    };
+    Some(key.unwrap_or_else(|| id.to_string()))
+}
+
+/// Key of a pet from its `type` and `tier`, like the pets of a profile or the `petInfo` of a pet item
+pub fn pet_key(pet: &Value) -> Option<String> {
+    Some(format!(
+        "PET;{};{}",
+        pet["type"].as_str()?,
+        pet["tier"].as_str()?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pets_are_keyed_by_type_and_tier() {
+        let pet_info = json!({
+            "type": "ENDER_DRAGON",
+            "active": false,
+            "exp": 25353230.0,
+            "tier": "LEGENDARY",
+            "hideInfo": false,
+            "heldItem": "PET_ITEM_TIER_BOOST"
+        });
+        let extra_attributes = json!({
+            "id": "PET",
+            "petInfo": pet_info.to_string(),
+            "uuid": "5d2e8f67-8d9b-4b1f-9f25-1e5d3a4b2c10",
+            "timestamp": 1690000000000i64
+        });
+        assert_eq!(
+            item_key(&extra_attributes).as_deref(),
+            Some("PET;ENDER_DRAGON;LEGENDARY")
+        );
+        assert_eq!(
+            pet_key(&pet_info).as_deref(),
+            Some("PET;ENDER_DRAGON;LEGENDARY")
+        );
+
+        // Pets of a profile have the same type and tier as pet items
+        let profile_pet = json!({"type": "ENDER_DRAGON", "tier": "LEGENDARY", "exp": 0.0});
+        assert_eq!(pet_key(&profile_pet), item_key(&extra_attributes));
+    }
+
+    #[test]
+    fn pets_with_unreadable_info_are_keyed_by_their_id() {
+        let extra_attributes = json!({"id": "PET", "petInfo": "{"});
+        assert_eq!(item_key(&extra_attributes).as_deref(), Some("PET"));
+        assert_eq!(pet_key(&json!({"type": "BEE"})), None);
+    }
+
+    #[test]
+    fn enchanted_books_are_keyed_by_their_single_enchantment() {
+        let book = json!({
+            "id": "ENCHANTED_BOOK",
+            "enchantments": {"ultimate_wise": 5},
+            "timestamp": "8/1/23 12:00 PM"
+        });
+        assert_eq!(
+            item_key(&book).as_deref(),
+            Some("ENCHANTED_BOOK;ULTIMATE_WISE;5")
+        );
+
+        let combined = json!({
+            "id": "ENCHANTED_BOOK",
+            "enchantments": {"sharpness": 5, "critical": 5}
+        });
+        assert_eq!(item_key(&combined).as_deref(), Some("ENCHANTED_BOOK"));
+    }
+
+    #[test]
+    fn runes_are_keyed_by_rune_and_level() {
+        let rune = json!({"id": "RUNE", "runes": {"MUSIC": 3}});
+        assert_eq!(item_key(&rune).as_deref(), Some("RUNE;MUSIC;3"));
+        let unique = json!({"id": "UNIQUE_RUNE", "runes": {"GRAND_SEARING": 3}});
+        assert_eq!(
+            item_key(&unique).as_deref(),
+            Some("UNIQUE_RUNE;GRAND_SEARING;3")
+        );
+    }
+
+    #[test]
+    fn other_items_are_keyed_by_their_id() {
+        let sword = json!({
+            "id": "HYPERION",
+            "enchantments": {"ultimate_wise": 5},
+            "modifier": "heroic"
+        });
+        assert_eq!(item_key(&sword).as_deref(), Some("HYPERION"));
+        assert_eq!(item_key(&json!({})), None);
+    }
+
+    #[test]
+    fn bazaar_prices_come_before_the_lowest_bin() {
+        let lowest_bin = HashMap::from([
+            ("ENCHANTED_DIAMOND".to_string(), 1000.0),
+            ("HYPERION".to_string(), 900_000_000.0),
+            ("DEAD_PRODUCT".to_string(), 50.0),
+        ]);
+        let prices = Prices {
+            bazaar: HashMap::from([
+                ("ENCHANTED_DIAMOND".to_string(), 800.0),
+                ("DEAD_PRODUCT".to_string(), 0.0),
+            ]),
+            lowest_bin: &lowest_bin,
+        };
+        assert_eq!(prices.price("ENCHANTED_DIAMOND"), Some(800.0));
+        assert_eq!(prices.price("HYPERION"), Some(900_000_000.0));
+        assert_eq!(prices.price("DEAD_PRODUCT"), Some(50.0));
+        assert_eq!(prices.price("DIRT"), None);
+    }
+}
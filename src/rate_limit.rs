@@ -0,0 +1,337 @@
+use crate::{
+    auth::client_key,
+    client_ip::{client_ip, rate_limit_key},
+    config::Config,
+    endpoints::hypixel_request,
+    error::WebError,
+    structs::WebData,
+};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    http::{
+        header::{HeaderName, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    },
+    web::Data,
+    Error, HttpRequest, HttpResponse,
+};
+use futures::future::LocalBoxFuture;
+use governor::{
+    clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
+    state::keyed::DefaultKeyedStateStore,
+    NegativeMultiDecision, Quota, RateLimiter,
+};
+use std::{
+    collections::HashMap,
+    future::{ready, Ready},
+    num::NonZeroU32,
+    rc::Rc,
+    time::Duration,
+};
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_AFTER: HeaderName = HeaderName::from_static("x-ratelimit-after");
+const X_RATELIMIT_WHITELISTED: HeaderName = HeaderName::from_static("x-ratelimit-whitelisted");
+
+/// Replenish period (milliseconds) and burst size when only one of them is set
+const DEFAULT_PERIOD: u64 = 500;
+const DEFAULT_BURST: u32 = 8;
+
+type Limiter =
+    RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock, StateInformationMiddleware>;
+
+fn limiter(period: Option<u64>, burst: Option<u32>) -> Limiter {
+    let period = Duration::from_millis(period.unwrap_or(DEFAULT_PERIOD));
+    let burst = NonZeroU32::new(burst.unwrap_or(DEFAULT_BURST)).unwrap();
+    RateLimiter::keyed(Quota::with_period(period).unwrap().allow_burst(burst))
+        .with_middleware::<StateInformationMiddleware>()
+}
+
+/// Result of taking requests from the quota of a client
+pub enum Outcome {
+    /// No quota applies to the client
+    Unlimited,
+    Allowed {
+        limit: u32,
+        remaining: u32,
+    },
+    /// The quota is exhausted until `wait` has passed
+    Limited {
+        limit: u32,
+        wait: Duration,
+    },
+    /// More requests than the quota can ever cover
+    TooLarge {
+        limit: u32,
+    },
+    /// The client couldn't be identified
+    Unidentified,
+}
+
+pub struct RateLimits {
+    /// From the `SERVER.PERIOD` and `SERVER.BURST` settings, `None` when neither is set so requests are never rejected
+    default: Option<Limiter>,
+    /// Quotas of the client keys that set their own period or burst
+    clients: HashMap<String, Limiter>,
+}
+
+impl RateLimits {
+    pub fn new(config: &Config) -> RateLimits {
+        let server = &config.server;
+        RateLimits {
+            default: (server.period.is_some() || server.burst.is_some())
+                .then(|| limiter(server.period, server.burst)),
+            clients: config
+                .client_keys
+                .iter()
+                .filter(|client| client.period.is_some() || client.burst.is_some())
+                .map(|client| {
+                    let quota = limiter(
+                        client.period.or(server.period),
+                        client.burst.or(server.burst),
+                    );
+                    (client.key.clone(), quota)
+                })
+                .collect(),
+        }
+    }
+
+    /// Takes `n` requests from the quota of the client that sent `req`, nothing is taken unless all of them fit
+    pub fn check(&self, req: &HttpRequest, config: &Config, n: u32) -> Outcome {
+        let client_key = client_key(req);
+        let Some(limiter) = client_key
+            .as_ref()
+            .and_then(|key| self.clients.get(key))
+            .or(self.default.as_ref())
+        else {
+            return Outcome::Unlimited;
+        };
+        let Some(n) = NonZeroU32::new(n) else {
+            return Outcome::Unlimited;
+        };
+
+        // Unknown keys are rejected before reaching the rate limiter
+        let key = match client_key {
+            Some(key) => format!("key:{key}"),
+            None => match client_ip(req, config) {
+                Some(client) => rate_limit_key(&client, config),
+                None => return Outcome::Unidentified,
+            },
+        };
+
+        match limiter.check_key_n(&key, n) {
+            Ok(snapshot) => Outcome::Allowed {
+                limit: snapshot.quota().burst_size().get(),
+                remaining: snapshot.remaining_burst_capacity(),
+            },
+            Err(NegativeMultiDecision::BatchNonConforming(_, not_until)) => Outcome::Limited {
+                limit: not_until.quota().burst_size().get(),
+                wait: not_until.wait_time_from(DefaultClock::default().now()),
+            },
+            Err(NegativeMultiDecision::InsufficientCapacity(limit)) => Outcome::TooLarge { limit },
+        }
+    }
+}
+
+/// Takes `n` more requests from the quota of the client, for handlers that make a request upstream
+/// per item. The request itself already took one when it went through the rate limiter.
+pub fn charge(web_data: &WebData, req: &HttpRequest, n: usize) -> Result<(), WebError> {
+    let n = u32::try_from(n).unwrap_or(u32::MAX);
+    let outcome = web_data
+        .rate_limits
+        .load()
+        .check(req, &web_data.config.load(), n);
+    match outcome {
+        Outcome::Limited { wait, .. } => Err(WebError {
+            retry_after: Some(wait.as_secs().max(1)),
+            ..WebError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_requests",
+                format!("This request needs {n} more requests than the quota has left"),
+            )
+        }),
+        Outcome::TooLarge { limit } => Err(WebError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "too_many_requests",
+            format!("This request needs {n} more requests but the quota allows at most {limit}"),
+        )),
+        Outcome::Unlimited | Outcome::Allowed { .. } | Outcome::Unidentified => Ok(()),
+    }
+}
+
+/// Whether a request doesn't use any quota
+async fn is_exempt(web_data: &WebData, req: &ServiceRequest) -> bool {
+    // Probes are cheap (readiness is cached) and shouldn't be rejected by the rate limiter
+    if matches!(req.path(), "/health" | "/ready") {
+        return true;
+    }
+
+    // Requests served from the cache don't use any quota
+    match hypixel_request(web_data, req.path(), req.query_string()) {
+        Some((path, params)) => web_data.api.is_cached(&path, &params).await,
+        None => false,
+    }
+}
+
+/// Rate limits requests with the current `WebData::rate_limits`, so that new quotas apply as soon
+/// as the config is reloaded
+pub struct ReloadableGovernor {
+    web_data: Data<WebData>,
+}
+
+impl ReloadableGovernor {
+    pub fn new(web_data: Data<WebData>) -> ReloadableGovernor {
+        ReloadableGovernor { web_data }
+    }
+}
+
+pub struct ReloadableGovernorMiddleware<S> {
+    service: Rc<S>,
+    web_data: Data<WebData>,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ReloadableGovernor
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ReloadableGovernorMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ReloadableGovernorMiddleware {
+            service: Rc::new(service),
+            web_data: self.web_data.clone(),
+        }))
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for ReloadableGovernorMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let web_data = self.web_data.clone();
+
+        Box::pin(async move {
+            if is_exempt(&web_data, &req).await {
+                let mut res = service.call(req).await?;
+                res.headers_mut()
+                    .insert(X_RATELIMIT_WHITELISTED, HeaderValue::from_static("true"));
+                return Ok(res.map_into_left_body());
+            }
+
+            let outcome =
+                web_data
+                    .rate_limits
+                    .load()
+                    .check(req.request(), &web_data.config.load(), 1);
+            let (limit, remaining) = match outcome {
+                Outcome::Unlimited => return Ok(service.call(req).await?.map_into_left_body()),
+                Outcome::Allowed { limit, remaining } => (limit, remaining),
+                Outcome::Limited { limit, wait } => {
+                    let wait = wait.as_secs();
+                    let res = HttpResponse::TooManyRequests()
+                        .insert_header((X_RATELIMIT_AFTER, wait))
+                        .insert_header((X_RATELIMIT_LIMIT, limit))
+                        .insert_header((X_RATELIMIT_REMAINING, 0))
+                        .insert_header((RETRY_AFTER, wait.max(1)))
+                        .body(format!("Too many requests, retry in {wait}s"));
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+                Outcome::TooLarge { .. } => unreachable!("a single request fits in any quota"),
+                Outcome::Unidentified => {
+                    return Err(ErrorInternalServerError(
+                        "Could not extract peer IP address from request",
+                    ))
+                }
+            };
+
+            let mut res = service.call(req).await?;
+            let headers = res.headers_mut();
+            headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(limit));
+            headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(remaining));
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+    };
+    use serde_json::json;
+
+    const SETTINGS: &str = "[SERVER]\nPERIOD = 60000\nBURST = 1\n[SERVER.ENDPOINT]\nCOUNTS = true";
+
+    fn counts() -> TestRequest {
+        TestRequest::get()
+            .uri("/counts")
+            .peer_addr("1.2.3.4:5000".parse().unwrap())
+    }
+
+    fn mock() -> MockUpstream {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::ok(json!({"success": true, "games": {}, "playerCount": 0})),
+        );
+        mock
+    }
+
+    #[actix_web::test]
+    async fn cached_requests_do_not_use_the_quota() {
+        let mock = mock();
+        let web_data = mock
+            .web_data(config(&format!(
+                "{SETTINGS}\n[HYPIXEL_CACHE_TTL]\nCOUNTS = 60"
+            )))
+            .await;
+        web_data.api.get_counts().await.unwrap();
+        let app = init_service(crate::app(web_data)).await;
+
+        for _ in 0..3 {
+            let res = call_service(&app, counts().to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(
+                res.headers().get("x-ratelimit-whitelisted").unwrap(),
+                "true"
+            );
+        }
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 1);
+    }
+
+    #[actix_web::test]
+    async fn uncached_requests_are_limited() {
+        let mock = mock();
+        let web_data = mock.web_data(config(SETTINGS)).await;
+        let app = init_service(crate::app(web_data)).await;
+
+        let res = call_service(&app, counts().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "0");
+
+        let res = call_service(&app, counts().to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().get("retry-after").is_some());
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 1);
+    }
+}
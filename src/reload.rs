@@ -0,0 +1,216 @@
+use crate::{
+    config::{shadowed_by_env, Config, ConfigError},
+    error::WebError,
+    rate_limit::RateLimits,
+    structs::WebData,
+};
+use actix_web::{http::StatusCode, post, web::Data, HttpResponse, Responder, ResponseError};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Changes of a reload that weren't applied
+#[derive(Debug)]
+pub struct Reloaded {
+    /// Changed settings that keep their current value until a restart
+    pub restart_required: Vec<&'static str>,
+    /// Settings of the file overridden by environment variables, which are only read at startup
+    pub shadowed_by_env: Vec<String>,
+}
+
+/// Re-reads the config file and swaps the enabled endpoints, cache TTLs, client keys, and rate limits.
+/// Requests in flight finish with the settings they started with.
+pub fn reload(web_data: &WebData) -> Result<Reloaded, ConfigError> {
+    let new = Config::load(web_data.config_file.as_deref())?;
+    let current = web_data.config.load();
+
+    let reloaded = Reloaded {
+        restart_required: new.restart_required(&current),
+        shadowed_by_env: web_data
+            .config_file
+            .as_deref()
+            .map(shadowed_by_env)
+            .unwrap_or_default(),
+    };
+    if !reloaded.restart_required.is_empty() {
+        warn!(
+            "Changes to {} are only applied after a restart",
+            reloaded.restart_required.join(", ")
+        );
+    }
+    if !reloaded.shadowed_by_env.is_empty() {
+        warn!(
+            "{} are set by environment variables, which aren't reloaded",
+            reloaded.shadowed_by_env.join(", ")
+        );
+    }
+
+    let config = current.with_reloadable(&new);
+    web_data.api.set_cache_ttls(config.cache_ttls());
+    // Swapping the rate limiter resets its state, so it is kept unless its settings changed
+    if config.server.period != current.server.period
+        || config.server.burst != current.server.burst
+        || config.client_keys != current.client_keys
+    {
+        web_data
+            .rate_limits
+            .store(Arc::new(RateLimits::new(&config)));
+    }
+    web_data.config.store(Arc::new(config));
+
+    info!("Reloaded config");
+    Ok(reloaded)
+}
+
+/// Reloads the config whenever the process receives SIGHUP
+#[cfg(unix)]
+pub async fn reload_on_hangup(web_data: Data<WebData>) {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            error!("Unable to listen for SIGHUP: {err}");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        if let Err(err) = reload(&web_data) {
+            error!("{err}");
+        }
+    }
+}
+
+#[post("/admin/reload")]
+pub async fn reload_config(web_data: Data<WebData>) -> impl Responder {
+    match reload(&web_data) {
+        Ok(reloaded) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "restart_required": reloaded.restart_required,
+            "shadowed_by_env": reloaded.shadowed_by_env,
+        })),
+        Err(err) => {
+            error!("{err}");
+            WebError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_config",
+                err.to_string(),
+            )
+            .error_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{MockResponse, MockUpstream};
+    use actix_web::{
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use serde_json::Value;
+    use std::{env, fs, path::Path};
+    use uuid::Uuid;
+
+    fn write_config(path: &Path, toml: &str) {
+        fs::write(
+            path,
+            format!("BASE_URL = \"127.0.0.1\"\nPORT = 8000\nAPI_KEYS = \"test-key\"\n{toml}"),
+        )
+        .unwrap();
+    }
+
+    /// Server state whose config is read from a file at `path`
+    async fn web_data(mock: &MockUpstream, path: &Path, toml: &str) -> Data<WebData> {
+        write_config(path, toml);
+        let web_data = mock
+            .web_data(Config::load(Some(path)).unwrap_or_else(|err| panic!("{err}")))
+            .await;
+        let mut web_data = Arc::into_inner(web_data.into_inner()).unwrap();
+        web_data.config_file = Some(path.to_path_buf());
+        Data::new(web_data)
+    }
+
+    #[actix_web::test]
+    async fn reloading_swaps_endpoints_ttls_and_rate_limits() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            "api.hypixel.net/counts",
+            MockResponse::ok(json!({"success": true, "games": {}, "playerCount": 0})),
+        );
+        let path = env::temp_dir().join(format!("rs-pixel-backend-{}.toml", Uuid::new_v4()));
+        let web_data = web_data(
+            &mock,
+            &path,
+            "[SERVER]\nPERIOD = 60000\nBURST = 1\n[SERVER.ENDPOINT]\nCOUNTS = true",
+        )
+        .await;
+        let rate_limit = web_data.rate_limits.load_full();
+
+        write_config(
+            &path,
+            "[HYPIXEL_CACHE_TTL]\nCOUNTS = 60\n\
+            [SERVER]\nPERIOD = 60000\nBURST = 3\n\
+            [SERVER.ENDPOINT]\nCOUNTS = true\nSTATUS = true",
+        );
+        let reloaded = reload(&web_data).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(reloaded.restart_required.is_empty());
+        assert!(reloaded.shadowed_by_env.is_empty());
+
+        let config = web_data.config.load();
+        assert_eq!(config.server.burst, Some(3));
+        assert_eq!(config.server.endpoint.get("STATUS"), Some(&true));
+        assert!(!Arc::ptr_eq(&rate_limit, &web_data.rate_limits.load_full()));
+        for _ in 0..2 {
+            web_data.api.get_counts().await.unwrap();
+        }
+        assert_eq!(mock.hits("api.hypixel.net/counts"), 1);
+    }
+
+    #[actix_web::test]
+    async fn restart_only_changes_are_reported_but_not_applied() {
+        let mock = MockUpstream::start();
+        let path = env::temp_dir().join(format!("rs-pixel-backend-{}.toml", Uuid::new_v4()));
+        let web_data = web_data(&mock, &path, "[SERVER]\nBURST = 10").await;
+
+        write_config(
+            &path,
+            "API_POOL_SIZE = 3\nLOG_LEVEL = \"debug\"\n[SERVER]\nBURST = 20",
+        );
+        let reloaded = reload(&web_data).unwrap();
+        assert_eq!(reloaded.restart_required, ["API_POOL_SIZE", "LOG_LEVEL"]);
+        let config = web_data.config.load();
+        assert_eq!(config.server.burst, Some(20));
+        assert_ne!(config.api_pool_size, 3);
+        assert_eq!(config.log_level, "info");
+
+        // Invalid files are rejected as a whole
+        write_config(&path, "API_POOL_SIZE = 0\n[SERVER]\nBURST = 30");
+        assert!(reload(&web_data).is_err());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(web_data.config.load().server.burst, Some(20));
+    }
+
+    #[actix_web::test]
+    async fn settings_overridden_by_the_environment_are_reported() {
+        let mock = MockUpstream::start();
+        let path = env::temp_dir().join(format!("rs-pixel-backend-{}.toml", Uuid::new_v4()));
+        // Only read at startup, so other tests don't depend on it
+        env::set_var("LOG_FORMAT", "Json");
+        let web_data = web_data(&mock, &path, "LOG_FORMAT = \"Pretty\"").await;
+        let app = init_service(App::new().app_data(web_data).service(reload_config)).await;
+
+        let res = call_service(&app, TestRequest::post().uri("/admin/reload").to_request()).await;
+        env::remove_var("LOG_FORMAT");
+        fs::remove_file(&path).unwrap();
+
+        assert!(res.status().is_success());
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["restart_required"], json!([]));
+        assert_eq!(body["shadowed_by_env"], json!(["LOG_FORMAT"]));
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    error::WebError,
+    prices::item_key,
+    structs::{PricesQuery, WebData},
+    utils::bad_request,
+};
+use actix_web::{
+    get,
+    http::StatusCode,
+    web::{Data, Path, Query},
+    HttpResponse, Responder, ResponseError,
+};
+use rs_pixel::{
+    response::skyblock::skyblock_auctions_ended_response::SkyblockAuctionEnded,
+    util::utils::get_timestamp_millis,
+};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    error::Error,
+    fs,
+    path::Path as FilePath,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Windows of `/skyblock/prices` in days, sales older than the longest one are deleted
+const WINDOWS: [(&str, i64); 3] = [("1d", 1), ("7d", 7), ("30d", 30)];
+
+/// A sold auction
+struct Sale {
+    auction_id: String,
+    /// `item_key` of the item
+    item: String,
+    /// Price of all of the items
+    price: i64,
+    count: i64,
+    bin: bool,
+    timestamp: i64,
+}
+
+impl Sale {
+    fn new(auction: &SkyblockAuctionEnded) -> Option<Sale> {
+        let item = auction.get_nbt()?.get(0)?.clone();
+        Some(Sale {
+            auction_id: auction.auction_id.clone(),
+            item: item_key(&item["tag"]["ExtraAttributes"])?,
+            price: auction.price,
+            count: item["Count"].as_i64().unwrap_or(1).max(1),
+            bin: auction.bin,
+            timestamp: auction.timestamp,
+        })
+    }
+}
+
+/// Sale price statistics of an item, per item
+#[derive(Serialize, Debug, PartialEq)]
+struct PriceStats {
+    sales: usize,
+    /// Items sold
+    volume: i64,
+    average: Option<f64>,
+    median: Option<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+/// Ended auctions recorded in a SQLite database, since Hypixel only lists the last minute of them
+pub struct Sales {
+    connection: Arc<Mutex<Connection>>,
+    /// `lastUpdated` of the last ended auctions that were recorded
+    last_updated: AtomicI64,
+}
+
+impl Sales {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: &FilePath) -> Result<Sales, Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS sales (
+                auction_id TEXT PRIMARY KEY,
+                item TEXT NOT NULL,
+                price INTEGER NOT NULL,
+                count INTEGER NOT NULL,
+                bin INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS sales_item_timestamp ON sales (item, timestamp);",
+        )?;
+
+        Ok(Sales {
+            connection: Arc::new(Mutex::new(connection)),
+            last_updated: AtomicI64::new(0),
+        })
+    }
+
+    /// Records the auctions that ended since the last call, auctions that were already recorded are skipped
+    async fn ingest(&self, web_data: &WebData) -> Result<(), String> {
+        let res = web_data
+            .api
+            .get_skyblock_auctions_ended_fresh()
+            .await
+            .map_err(|err| err.to_string())?;
+        if self.last_updated.load(Ordering::Relaxed) == res.last_updated {
+            return Ok(());
+        }
+
+        let connection = self.connection.clone();
+        let last_updated = res.last_updated;
+        actix_web::rt::task::spawn_blocking(move || {
+            let sales = res
+                .auctions
+                .iter()
+                .filter_map(Sale::new)
+                .collect::<Vec<_>>();
+            let recorded = record(
+                &mut connection.lock().unwrap(),
+                &sales,
+                get_timestamp_millis(),
+            )?;
+            debug!("Recorded {recorded} sales");
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+        self.last_updated.store(last_updated, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Price of one item and the number of items of each sale of an item since `since`
+    async fn unit_prices(&self, item: String, since: i64) -> Result<Vec<(f64, i64)>, String> {
+        let connection = self.connection.clone();
+        actix_web::rt::task::spawn_blocking(move || {
+            connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "SELECT price, count FROM sales WHERE item = ?1 AND timestamp >= ?2",
+                )?
+                .query_map(params![item, since], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+                })?
+                .map(|row| row.map(|(price, count)| (price as f64 / count as f64, count)))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+    }
+}
+
+/// Inserts the sales that weren't recorded yet and deletes those older than the longest window,
+/// returns the number of sales inserted
+fn record(connection: &mut Connection, sales: &[Sale], now: i64) -> rusqlite::Result<usize> {
+    let retention = WINDOWS.iter().map(|(_, days)| days).max().unwrap() * DAY_MILLIS;
+    let transaction = connection.transaction()?;
+    let mut recorded = 0;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT OR IGNORE INTO sales (auction_id, item, price, count, bin, timestamp)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for sale in sales {
+            recorded += insert.execute(params![
+                sale.auction_id,
+                sale.item,
+                sale.price,
+                sale.count,
+                sale.bin,
+                sale.timestamp
+            ])?;
+        }
+    }
+    transaction.execute(
+        "DELETE FROM sales WHERE timestamp < ?1",
+        params![now - retention],
+    )?;
+    transaction.commit()?;
+    Ok(recorded)
+}
+
+/// Days of a window of `/skyblock/prices`
+fn window_days(window: &str) -> Option<i64> {
+    WINDOWS
+        .iter()
+        .find(|(name, _)| *name == window)
+        .map(|(_, days)| *days)
+}
+
+/// Statistics of the price of one item and the number of items of each sale, weighted by
+/// the number of items so that a sale of 64 items counts as 64 sales of one
+fn price_stats(mut sold: Vec<(f64, i64)>) -> PriceStats {
+    sold.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    let volume = sold.iter().map(|(_, count)| count).sum::<i64>();
+    // Price of the item at `idx` when every item sold is sorted by price
+    let nth = |idx: i64| {
+        let mut items = 0;
+        sold.iter()
+            .find(|(_, count)| {
+                items += count;
+                items > idx
+            })
+            .map(|(price, _)| *price)
+    };
+    let median = match volume {
+        0 => None,
+        volume if volume % 2 == 0 => nth(volume / 2 - 1)
+            .zip(nth(volume / 2))
+            .map(|(lower, upper)| (lower + upper) / 2.0),
+        volume => nth(volume / 2),
+    };
+    let coins = sold
+        .iter()
+        .map(|(price, count)| price * *count as f64)
+        .sum::<f64>();
+
+    PriceStats {
+        sales: sold.len(),
+        volume,
+        average: (volume > 0).then(|| coins / volume as f64),
+        median,
+        min: sold.first().map(|(price, _)| *price),
+        max: sold.last().map(|(price, _)| *price),
+    }
+}
+
+/// Records ended auctions every `SALES_POLL_INTERVAL` seconds
+pub async fn record_sales(web_data: Data<WebData>, interval: Duration) {
+    let Some(sales) = &web_data.sales else {
+        return;
+    };
+    loop {
+        if let Err(err) = sales.ingest(&web_data).await {
+            warn!("Unable to record ended auctions: {err}");
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+/// Sale price statistics of an item key over a window of recorded ended auctions
+#[get("/skyblock/prices/{item_id}")]
+pub async fn skyblock_prices(
+    web_data: Data<WebData>,
+    item_id: Path<String>,
+    query: Query<PricesQuery>,
+) -> impl Responder {
+    let Some(sales) = &web_data.sales else {
+        return WebError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "sales_unavailable",
+            "Sales aren't recorded, set SALES_DB to record them",
+        )
+        .error_response();
+    };
+    let window = query.window.as_deref().unwrap_or("1d");
+    let Some(days) = window_days(window) else {
+        return bad_request("Invalid window provided");
+    };
+
+    let item = item_id.into_inner().to_uppercase();
+    let since = get_timestamp_millis() - days * DAY_MILLIS;
+    let sold = match sales.unit_prices(item.clone(), since).await {
+        Ok(sold) => sold,
+        Err(err) => {
+            warn!("Unable to read recorded sales: {err}");
+            return WebError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Unable to read recorded sales",
+            )
+            .error_response();
+        }
+    };
+    let stats = price_stats(sold);
+
+    let mut res = json!(stats);
+    res["success"] = json!(true);
+    res["item"] = json!(item);
+    res["window"] = json!(window);
+    HttpResponse::Ok().json(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+
+    fn sale(auction_id: &str, price: i64, count: i64, timestamp: i64) -> Sale {
+        Sale {
+            auction_id: auction_id.to_string(),
+            item: "ENCHANTED_DIAMOND".to_string(),
+            price,
+            count,
+            bin: true,
+            timestamp,
+        }
+    }
+
+    fn recorded(sales: &Sales) -> Vec<String> {
+        sales
+            .connection
+            .lock()
+            .unwrap()
+            .prepare("SELECT auction_id FROM sales ORDER BY auction_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn median_of_an_odd_number_of_items() {
+        let stats = price_stats(vec![(30.0, 1), (10.0, 1), (20.0, 1)]);
+        assert_eq!(stats.median, Some(20.0));
+        assert_eq!(stats.average, Some(20.0));
+        assert_eq!((stats.min, stats.max), (Some(10.0), Some(30.0)));
+    }
+
+    #[test]
+    fn median_of_an_even_number_of_items() {
+        let stats = price_stats(vec![(40.0, 1), (10.0, 1), (20.0, 1), (30.0, 1)]);
+        assert_eq!(stats.median, Some(25.0));
+        assert_eq!(stats.average, Some(25.0));
+    }
+
+    #[test]
+    fn stats_are_weighted_by_the_items_of_each_sale() {
+        let stats = price_stats(vec![(100.0, 1), (10.0, 64), (1000.0, 1)]);
+        assert_eq!(stats.sales, 3);
+        assert_eq!(stats.volume, 66);
+        assert_eq!(stats.median, Some(10.0));
+        assert_eq!(stats.average, Some((100.0 + 640.0 + 1000.0) / 66.0));
+
+        // The two middle items of 4 belong to different sales
+        let stats = price_stats(vec![(10.0, 2), (20.0, 2)]);
+        assert_eq!(stats.median, Some(15.0));
+    }
+
+    #[test]
+    fn stats_without_sales_are_null() {
+        assert_eq!(
+            price_stats(Vec::new()),
+            PriceStats {
+                sales: 0,
+                volume: 0,
+                average: None,
+                median: None,
+                min: None,
+                max: None,
+            }
+        );
+    }
+
+    #[test]
+    fn windows_are_parsed_by_name() {
+        assert_eq!(window_days("1d"), Some(1));
+        assert_eq!(window_days("7d"), Some(7));
+        assert_eq!(window_days("30d"), Some(30));
+        assert_eq!(window_days("2d"), None);
+        assert_eq!(window_days("1D"), None);
+        assert_eq!(window_days(""), None);
+    }
+
+    #[test]
+    fn sales_are_recorded_once() {
+        let sales = Sales::open(FilePath::new(":memory:")).unwrap();
+        let now = 40 * DAY_MILLIS;
+        let mut connection = sales.connection.lock().unwrap();
+
+        let first = [sale("a", 100, 1, now), sale("b", 200, 2, now)];
+        assert_eq!(record(&mut connection, &first, now).unwrap(), 2);
+        let second = [sale("b", 999, 1, now), sale("c", 300, 1, now)];
+        assert_eq!(record(&mut connection, &second, now).unwrap(), 1);
+
+        let price: i64 = connection
+            .query_row(
+                "SELECT price FROM sales WHERE auction_id = 'b'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(price, 200);
+        drop(connection);
+        assert_eq!(recorded(&sales), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn sales_older_than_the_longest_window_are_deleted() {
+        let sales = Sales::open(FilePath::new(":memory:")).unwrap();
+        let now = 40 * DAY_MILLIS;
+        let old = [
+            sale("kept", 100, 1, now - 30 * DAY_MILLIS),
+            sale("deleted", 100, 1, now - 30 * DAY_MILLIS - 1),
+        ];
+        record(&mut sales.connection.lock().unwrap(), &old, now).unwrap();
+        assert_eq!(recorded(&sales), ["kept"]);
+    }
+
+    #[actix_web::test]
+    async fn unchanged_ended_auctions_are_skipped() {
+        let mock = MockUpstream::start();
+        let ended = |last_updated: i64| {
+            MockResponse::ok(json!({"success": true, "lastUpdated": last_updated, "auctions": []}))
+        };
+        mock.respond("api.hypixel.net/skyblock/auctions_ended", ended(1));
+        let web_data = mock.web_data(config("")).await;
+        let sales = Sales::open(FilePath::new(":memory:")).unwrap();
+        sales.ingest(&web_data).await.unwrap();
+
+        // Sales past their retention are only deleted when new ended auctions are recorded
+        record(
+            &mut sales.connection.lock().unwrap(),
+            &[sale("old", 100, 1, 0)],
+            0,
+        )
+        .unwrap();
+        sales.ingest(&web_data).await.unwrap();
+        assert_eq!(recorded(&sales), ["old"]);
+
+        mock.respond("api.hypixel.net/skyblock/auctions_ended", ended(2));
+        sales.ingest(&web_data).await.unwrap();
+        assert!(recorded(&sales).is_empty());
+        assert_eq!(mock.hits("api.hypixel.net/skyblock/auctions_ended"), 3);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{error::WebError, structs::WebData};
+use actix_web::http::StatusCode;
+use rs_pixel::{
+    response::skyblock::skyblock_profiles_response::SkyblockProfilesResponse,
+    util::skyblock_profile::SkyblockProfile,
+};
+use serde_json::{json, Value};
+
+/// Which of a player's profiles to use
+pub enum ProfileSelector<'a> {
+    /// The profile the player last played on
+    Selected,
+    /// The profile the player's data was saved to most recently
+    Latest,
+    /// The profile named like a fruit (e.g. Strawberry), case-insensitive
+    CuteName(&'a str),
+}
+
+impl ProfileSelector<'_> {
+    pub fn parse(profile: &str) -> ProfileSelector<'_> {
+        match profile {
+            "selected" => ProfileSelector::Selected,
+            "latest" => ProfileSelector::Latest,
+            cute_name => ProfileSelector::CuteName(cute_name),
+        }
+    }
+
+    /// Selects a profile of the player with the undashed `uuid`
+    pub fn select<'a>(
+        &self,
+        profiles: &'a SkyblockProfilesResponse,
+        uuid: &str,
+    ) -> Option<&'a SkyblockProfile> {
+        match self {
+            ProfileSelector::Selected => profiles.get_selected_profile(),
+            ProfileSelector::Latest => profiles
+                .profiles
+                .iter()
+                .max_by_key(|profile| profile.members[uuid]["last_save"].as_i64()),
+            ProfileSelector::CuteName(cute_name) => profiles.profiles.iter().find(|profile| {
+                profile
+                    .cute_name
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(cute_name))
+            }),
+        }
+    }
+}
+
+/// Fetches the profiles of the player with the undashed `uuid` and selects one of them
+pub async fn fetch_profile(
+    web_data: &WebData,
+    uuid: &str,
+    selector: &ProfileSelector<'_>,
+) -> Result<Value, WebError> {
+    let profiles = web_data
+        .api
+        .get_skyblock_profiles(uuid)
+        .await
+        .map_err(WebError::hypixel)?;
+
+    selector
+        .select(&profiles, uuid)
+        .map(|profile| json!(profile))
+        .ok_or_else(|| WebError::new(StatusCode::NOT_FOUND, "not_found", "No profile found"))
+}
+
+/// The first of the dot separated `paths` present in the member data of a profile, newer profiles
+/// moved some fields so the current path is listed before the legacy one
+pub fn property<'a>(member: &'a Value, paths: &[&str]) -> Option<&'a Value> {
+    paths.iter().find_map(|path| {
+        path.split('.')
+            .try_fold(member, |value, key| value.get(key))
+            .filter(|value| !value.is_null())
+    })
+}
+
+/// Removes the `§` formatting codes of an item name or lore line
+pub fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{config, MockResponse, MockUpstream};
+
+    const UUID: &str = "069a79f444e94726a5befca90e38aaf5";
+
+    fn profiles() -> Value {
+        let profile = |id: &str, cute_name: &str, selected: bool, last_save: i64| {
+            json!({
+                "profile_id": id,
+                "cute_name": cute_name,
+                "selected": selected,
+                "members": {UUID: {"last_save": last_save}}
+            })
+        };
+        json!({
+            "success": true,
+            "profiles": [
+                profile("apple", "Apple", false, 100),
+                profile("banana", "Banana", true, 50),
+                profile("cucumber", "Cucumber", false, 300),
+            ]
+        })
+    }
+
+    fn select(profile: &str) -> Option<String> {
+        let profiles = serde_json::from_value::<SkyblockProfilesResponse>(profiles()).unwrap();
+        ProfileSelector::parse(profile)
+            .select(&profiles, UUID)
+            .map(|profile| profile.profile_id.clone())
+    }
+
+    #[test]
+    fn profiles_are_selected_by_selector() {
+        assert_eq!(select("selected").as_deref(), Some("banana"));
+        assert_eq!(select("latest").as_deref(), Some("cucumber"));
+        assert_eq!(select("Apple").as_deref(), Some("apple"));
+    }
+
+    #[test]
+    fn cute_names_are_case_insensitive() {
+        assert_eq!(select("apple").as_deref(), Some("apple"));
+        assert_eq!(select("CUCUMBER").as_deref(), Some("cucumber"));
+        assert_eq!(select("Kiwi"), None);
+    }
+
+    #[actix_web::test]
+    async fn unknown_cute_names_are_not_found() {
+        let mock = MockUpstream::start();
+        mock.respond(
+            &format!("api.hypixel.net/skyblock/profiles?uuid={UUID}"),
+            MockResponse::ok(profiles()),
+        );
+        let web_data = mock.web_data(config("")).await;
+
+        let profile = fetch_profile(&web_data, UUID, &ProfileSelector::parse("banana"))
+            .await
+            .unwrap();
+        assert_eq!(profile["cute_name"], "Banana");
+        let err = fetch_profile(&web_data, UUID, &ProfileSelector::parse("Kiwi"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        assert_eq!(err.code, "not_found");
+    }
+
+    #[test]
+    fn formatting_codes_are_stripped() {
+        assert_eq!(
+            strip_formatting("§6§lLegendary §r§aSword"),
+            "Legendary Sword"
+        );
+        assert_eq!(strip_formatting("§c✪✪✪ Hyperion§"), "✪✪✪ Hyperion");
+        assert_eq!(strip_formatting("Plain"), "Plain");
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{
+    error::WebError,
+    extractors::ResolvedPlayer,
+    skyblock::{fetch_profile, property, ProfileSelector},
+    structs::{SkyblockProfilesQuery, WebData},
+};
+use actix_web::{
+    get,
+    web::{Data, Query},
+    HttpResponse, Responder, ResponseError,
+};
+use rs_pixel::{
+    util::constants::{BLAZE_EXP, CATACOMBS_EXP, ENDERMAN_EXP, SPIDER_EXP, WOLF_EXP, ZOMBIE_EXP},
+    HypixelEndpoint,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Skills left out of the skill average
+const COSMETIC_SKILLS: [&str; 2] = ["RUNECRAFTING", "SOCIAL"];
+
+const DUNGEON_CLASSES: [&str; 5] = ["healer", "mage", "berserk", "archer", "tank"];
+
+/// Farming is capped below its max level until the cap is raised with Jacob's perks
+const FARMING_BASE_CAP: usize = 50;
+
+#[derive(Serialize)]
+struct Level {
+    level: usize,
+    max_level: usize,
+    experience: f64,
+    /// Progress towards the next level, from 0 to 1
+    progress: f64,
+}
+
+impl Level {
+    /// `totals` is the total experience required for each level
+    fn new(experience: f64, totals: &[f64], max_level: usize) -> Level {
+        let max_level = max_level.min(totals.len());
+        let level = totals[..max_level]
+            .iter()
+            .take_while(|total| **total <= experience)
+            .count();
+        let progress = if level < max_level {
+            let previous = if level > 0 { totals[level - 1] } else { 0.0 };
+            ((experience - previous) / (totals[level] - previous)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        Level {
+            level,
+            max_level,
+            experience,
+            progress,
+        }
+    }
+}
+
+/// Skill, slayer, and dungeon levels of a player on one of their profiles, computed from its member data
+#[get("/skyblock/stats")]
+pub async fn skyblock_stats(
+    web_data: Data<WebData>,
+    query: Query<SkyblockProfilesQuery>,
+    resolved: ResolvedPlayer,
+) -> impl Responder {
+    let selector = ProfileSelector::parse(query.profile.as_deref().unwrap_or("selected"));
+    let res = futures::try_join!(fetch_profile(&web_data, &resolved.uuid, &selector), async {
+        web_data
+            .api
+            .get_resources(HypixelEndpoint::RESOURCES_SKYBLOCK_SKILLS)
+            .await
+            .map_err(WebError::hypixel)
+    });
+    let (profile, skills) = match res {
+        Ok(res) => res,
+        Err(err) => return err.error_response(),
+    };
+
+    let member = &profile["members"][&resolved.uuid];
+    let skills = skill_levels(member, &skills["skills"]);
+    let skill_average = skills.as_ref().and_then(skill_average);
+
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "uuid": resolved.uuid,
+        "profile_id": profile["profile_id"],
+        "cute_name": profile["cute_name"],
+        "skills": skills,
+        "skill_average": skill_average,
+        "slayers": slayer_levels(member),
+        "catacombs": dungeon_level(member, "dungeons.dungeon_types.catacombs.experience"),
+        "classes": DUNGEON_CLASSES
+            .iter()
+            .map(|class| {
+                let path = format!("dungeons.player_classes.{class}.experience");
+                (class.to_string(), dungeon_level(member, &path))
+            })
+            .collect::<Map<_, _>>(),
+        "fairy_souls": property(member, &["fairy_soul.total_collected", "fairy_souls_collected"])
+            .and_then(Value::as_i64)
+            .unwrap_or(0),
+    }))
+}
+
+/// Levels by lowercase skill name from the `RESOURCES_SKYBLOCK_SKILLS` tables,
+/// `None` if the player disabled their skills API
+fn skill_levels(member: &Value, skills: &Value) -> Option<Map<String, Value>> {
+    let skills = skills.as_object()?;
+    let farming_cap = FARMING_BASE_CAP
+        + property(
+            member,
+            &[
+                "jacobs_contest.perks.farming_level_cap",
+                "jacob2.perks.farming_level_cap",
+            ],
+        )
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as usize;
+
+    let mut levels = Map::new();
+    for (name, skill) in skills {
+        let Some(experience) = property(
+            member,
+            &[
+                &format!("player_data.experience.SKILL_{name}"),
+                &format!("experience_skill_{}", name.to_lowercase()),
+            ],
+        )
+        .and_then(Value::as_f64) else {
+            continue;
+        };
+        let totals = skill["levels"]
+            .as_array()
+            .map(|levels| {
+                levels
+                    .iter()
+                    .filter_map(|level| level["totalExpRequired"].as_f64())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut max_level = skill["maxLevel"].as_u64().unwrap_or(0) as usize;
+        if name == "FARMING" {
+            max_level = max_level.min(farming_cap);
+        }
+
+        let level = Level::new(experience, &totals, max_level);
+        levels.insert(name.to_lowercase(), json!(level));
+    }
+
+    (!levels.is_empty()).then_some(levels)
+}
+
+fn skill_average(skills: &Map<String, Value>) -> Option<f64> {
+    let levels = skills
+        .iter()
+        .filter(|(name, _)| !COSMETIC_SKILLS.contains(&name.to_uppercase().as_str()))
+        .filter_map(|(_, skill)| skill["level"].as_f64())
+        .collect::<Vec<_>>();
+    (!levels.is_empty()).then(|| levels.iter().sum::<f64>() / levels.len() as f64)
+}
+
+fn slayer_levels(member: &Value) -> Map<String, Value> {
+    [
+        ("zombie", *ZOMBIE_EXP),
+        ("spider", *SPIDER_EXP),
+        ("wolf", *WOLF_EXP),
+        ("enderman", *ENDERMAN_EXP),
+        ("blaze", *BLAZE_EXP),
+    ]
+    .into_iter()
+    .map(|(name, totals)| {
+        let experience = experience(
+            member,
+            &[
+                &format!("slayer.slayer_bosses.{name}.xp"),
+                &format!("slayer_bosses.{name}.xp"),
+            ],
+        );
+        let totals = totals.iter().map(|total| *total as f64).collect::<Vec<_>>();
+        let level = Level::new(experience, &totals, totals.len());
+        (name.to_string(), json!(level))
+    })
+    .collect()
+}
+
+/// Catacombs and dungeon classes share the same levels
+fn dungeon_level(member: &Value, path: &str) -> Value {
+    let experience = experience(member, &[path]);
+    json!(Level::new(
+        experience,
+        &cumulative(*CATACOMBS_EXP),
+        CATACOMBS_EXP.len()
+    ))
+}
+
+/// Total experience required for each level from the experience required by each level
+fn cumulative(table: &[i64]) -> Vec<f64> {
+    table
+        .iter()
+        .scan(0.0, |total, exp| {
+            *total += *exp as f64;
+            Some(*total)
+        })
+        .collect()
+}
+
+fn experience(member: &Value, paths: &[&str]) -> f64 {
+    property(member, paths)
+        .and_then(Value::as_f64)
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A skill table of `max_level` levels, each one requiring 100 more experience
+    fn skill(max_level: usize) -> Value {
+        json!({
+            "maxLevel": max_level,
+            "levels": (1..=max_level)
+                .map(|level| json!({"level": level, "totalExpRequired": level * 100}))
+                .collect::<Vec<_>>()
+        })
+    }
+
+    fn level(level: &Value) -> (u64, f64) {
+        (
+            level["level"].as_u64().unwrap(),
+            level["progress"].as_f64().unwrap(),
+        )
+    }
+
+    #[test]
+    fn skill_levels_are_reached_at_their_threshold() {
+        let skills = json!({"MINING": skill(3)});
+        let mining = |experience: f64| {
+            let member = json!({"player_data": {"experience": {"SKILL_MINING": experience}}});
+            level(&skill_levels(&member, &skills).unwrap()["mining"])
+        };
+
+        assert_eq!(mining(0.0), (0, 0.0));
+        assert_eq!(mining(99.0), (0, 0.99));
+        assert_eq!(mining(100.0), (1, 0.0));
+        assert_eq!(mining(250.0), (2, 0.5));
+        assert_eq!(mining(300.0), (3, 0.0));
+        assert_eq!(mining(1e9), (3, 0.0));
+    }
+
+    #[test]
+    fn legacy_skill_experience_is_read() {
+        let member = json!({"experience_skill_mining": 100.0});
+        let skills = skill_levels(&member, &json!({"MINING": skill(3)})).unwrap();
+        assert_eq!(level(&skills["mining"]), (1, 0.0));
+    }
+
+    #[test]
+    fn farming_is_capped_until_the_cap_is_raised() {
+        let skills = json!({"FARMING": skill(60)});
+        let member = json!({"player_data": {"experience": {"SKILL_FARMING": 1e9}}});
+        let farming = &skill_levels(&member, &skills).unwrap()["farming"];
+        assert_eq!(farming["level"], 50);
+        assert_eq!(farming["max_level"], 50);
+
+        let member = json!({
+            "player_data": {"experience": {"SKILL_FARMING": 5500.0}},
+            "jacobs_contest": {"perks": {"farming_level_cap": 10}}
+        });
+        let farming = &skill_levels(&member, &skills).unwrap()["farming"];
+        assert_eq!(level(farming), (55, 0.0));
+        assert_eq!(farming["max_level"], 60);
+    }
+
+    #[test]
+    fn slayer_levels_use_cumulative_experience() {
+        let zombie = |experience: i64| {
+            let member = json!({"slayer": {"slayer_bosses": {"zombie": {"xp": experience}}}});
+            level(&slayer_levels(&member)["zombie"]).0
+        };
+
+        assert_eq!(zombie(0), 0);
+        assert_eq!(zombie(ZOMBIE_EXP[0]), 1);
+        assert_eq!(zombie(ZOMBIE_EXP[1] - 1), 1);
+        assert_eq!(zombie(ZOMBIE_EXP[1]), 2);
+        assert_eq!(zombie(ZOMBIE_EXP[ZOMBIE_EXP.len() - 1]), 9);
+        assert_eq!(zombie(i64::MAX), 9);
+    }
+
+    #[test]
+    fn dungeon_levels_add_up_the_experience_of_each_level() {
+        let catacombs = |experience: f64| {
+            let member =
+                json!({"dungeons": {"dungeon_types": {"catacombs": {"experience": experience}}}});
+            level(&dungeon_level(
+                &member,
+                "dungeons.dungeon_types.catacombs.experience",
+            ))
+        };
+        let first = CATACOMBS_EXP[0] as f64;
+        let second = first + CATACOMBS_EXP[1] as f64;
+        let max = CATACOMBS_EXP.iter().sum::<i64>() as f64;
+
+        assert_eq!(catacombs(first - 1.0).0, 0);
+        assert_eq!(catacombs(first), (1, 0.0));
+        assert_eq!(catacombs(second - 1.0).0, 1);
+        assert_eq!(catacombs(second), (2, 0.0));
+        assert_eq!(catacombs(max - 1.0).0, CATACOMBS_EXP.len() as u64 - 1);
+        assert_eq!(catacombs(max), (CATACOMBS_EXP.len() as u64, 0.0));
+        assert_eq!(catacombs(max * 2.0).0, CATACOMBS_EXP.len() as u64);
+    }
+}
@@ -1,58 +1,114 @@
-use rs_pixel::RsPixel;
+use crate::{
+    api::ApiPool, auctions::AuctionHouse, bazaar::BazaarHistory, config::Config, health::Readiness,
+    minecraft::Resolver, rate_limit::RateLimits, sales::Sales,
+};
+use arc_swap::ArcSwap;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use serde_json::Value;
+use std::path::PathBuf;
 
 pub struct WebData {
-    pub api: Mutex<RsPixel>,
+    pub api: ApiPool,
+    pub minecraft: Resolver,
+    pub auction_house: AuctionHouse,
+    /// Set when `SALES_DB` is
+    pub sales: Option<Sales>,
+    /// Set when `BAZAAR_DB` is
+    pub bazaar_history: Option<BazaarHistory>,
+    pub readiness: Readiness,
+    /// Swapped when the config is reloaded
+    pub config: ArcSwap<Config>,
+    pub config_file: Option<PathBuf>,
+    pub rate_limits: ArcSwap<RateLimits>,
 }
 
 #[derive(Serialize)]
-pub struct WebError {
+pub struct KeyHealth {
+    pub key: String,
+    pub valid: bool,
+    pub remaining: Option<i64>,
+    pub limit: Option<i64>,
+    pub time_till_reset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ComponentStatus {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cause: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReadyComponents {
+    pub config: ComponentStatus,
+    pub hypixel: ComponentStatus,
+    pub minecraft: ComponentStatus,
+    pub cache: ComponentStatus,
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
     pub success: bool,
-    pub cause: String,
+    pub checked_at: i64,
+    pub components: ReadyComponents,
+}
+
+#[derive(Deserialize)]
+pub struct ClientKeyQuery {
+    pub key: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct PlayerQuery {
     pub username: Option<String>,
     pub uuid: Option<String>,
+    pub player: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct GuildQuery {
-    pub id: Option<String>,
-    pub name: Option<String>,
-    pub player: Option<String>,
-    pub username: Option<String>,
+pub struct BatchOperation {
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub player: PlayerQuery,
 }
 
-#[derive(Deserialize)]
-pub struct StatusQuery {
-    pub username: Option<String>,
-    pub uuid: Option<String>,
+#[derive(Serialize)]
+pub struct BatchResult {
+    pub status: u16,
+    /// What the endpoint responds with when called on its own
+    pub response: Value,
 }
 
 #[derive(Deserialize)]
-pub struct RecentGamesQuery {
+pub struct MojangQuery {
     pub username: Option<String>,
     pub uuid: Option<String>,
+    /// Comma-separated fallback order of the Minecraft APIs
+    pub apis: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct SkyblockProfilesQuery {
-    pub username: Option<String>,
-    pub uuid: Option<String>,
+pub struct GuildQuery {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    /// `members` or `members.status`
+    pub expand: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct SkyblockProfileQuery {
+pub struct SkyblockProfilesQuery {
+    /// A cute name, `selected`, or `latest`
     pub profile: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct SkyblockBingoQuery {
-    pub username: Option<String>,
-    pub uuid: Option<String>,
+pub struct SkyblockProfileQuery {
+    /// Profile uuid
+    pub profile: Option<String>,
+    /// Selects a profile of the player like `SkyblockProfilesQuery::profile`
+    pub cute_name: Option<String>,
+    /// Replaces NBT inventories with their decoded items
+    pub decode_inventories: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -66,6 +122,35 @@ pub struct AuctionQuery {
 #[derive(Deserialize)]
 pub struct SkyblockAuctionQuery {
     pub page: Option<i64>,
+    /// Replaces the `item_bytes` of auctions with their decoded item
+    pub decode_inventories: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct AuctionSearchQuery {
+    /// Item key, or an item id to match every key of the id
+    pub item: Option<String>,
+    pub bin: Option<bool>,
+    pub max_price: Option<i64>,
+    pub sort: Option<String>,
+    pub page: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct PricesQuery {
+    /// `1d`, `7d`, or `30d`
+    pub window: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BazaarHistoryQuery {
+    /// Milliseconds since the epoch
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// `1m`, `1h`, or `1d`
+    pub resolution: Option<String>,
+    /// `json` or `csv`
+    pub format: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,267 @@
+//! Helpers shared by the tests: configs loaded from TOML and a mock of the upstream APIs
+
+use crate::{
+    api::ApiPool, auctions::AuctionHouse, cache::MemoryCache, config::Config, health::Readiness,
+    keys::KeyTracker, minecraft::Resolver, rate_limit::RateLimits, structs::WebData,
+};
+use actix_web::{
+    http::StatusCode,
+    web::{self, Data},
+    App, HttpRequest, HttpResponse, HttpServer,
+};
+use arc_swap::ArcSwap;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    env, fs,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use surf::{
+    middleware::{Middleware, Next},
+    utils::async_trait,
+    Client, Request, Response, Url,
+};
+use uuid::Uuid;
+
+/// Loads a config with the required settings followed by `toml`
+pub fn config(toml: &str) -> Config {
+    let path = env::temp_dir().join(format!("rs-pixel-backend-{}.toml", Uuid::new_v4()));
+    fs::write(
+        &path,
+        format!("BASE_URL = \"127.0.0.1\"\nPORT = 8000\nAPI_KEYS = \"test-key\"\n{toml}"),
+    )
+    .unwrap();
+    let config = Config::load(Some(&path));
+    fs::remove_file(&path).unwrap();
+    config.unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[derive(Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: Value,
+    pub headers: Vec<(&'static str, String)>,
+    /// Time the response is delayed by
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    pub fn ok(body: Value) -> MockResponse {
+        MockResponse {
+            status: 200,
+            body,
+            headers: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn status(status: u16, body: Value) -> MockResponse {
+        MockResponse {
+            status,
+            ..MockResponse::ok(body)
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl ToString) -> MockResponse {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> MockResponse {
+        self.delay = delay;
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    /// Keyed by `host/path` or `host/path?query`
+    routes: Mutex<HashMap<String, MockResponse>>,
+    /// Served once each before the response of their route
+    queued: Mutex<HashMap<String, VecDeque<MockResponse>>>,
+    hits: Mutex<HashMap<String, usize>>,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+/// An HTTP server standing in for Hypixel and the Minecraft APIs. Requests of clients from
+/// `MockUpstream::client` are redirected to it, `https://api.hypixel.net/counts` is served
+/// by the route `api.hypixel.net/counts`.
+pub struct MockUpstream {
+    url: Url,
+    state: Arc<MockState>,
+}
+
+impl MockUpstream {
+    /// Starts the server on the current actix system, every API key is valid
+    pub fn start() -> MockUpstream {
+        let state = Arc::new(MockState::default());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+        let data = Data::from(state.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .default_service(web::to(respond))
+        })
+        .workers(1)
+        .disable_signals()
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        let mock = MockUpstream { url, state };
+        mock.respond(
+            "api.hypixel.net/key",
+            MockResponse::ok(json!({
+                "success": true,
+                "record": {
+                    "key": "test-key",
+                    "owner": "00000000000000000000000000000000",
+                    "limit": 300,
+                    "queriesInPastMin": 0,
+                    "totalQueries": 0
+                }
+            })),
+        );
+        mock
+    }
+
+    pub fn respond(&self, route: &str, response: MockResponse) {
+        self.state
+            .routes
+            .lock()
+            .unwrap()
+            .insert(route.to_string(), response);
+    }
+
+    /// Serves `response` once, after the responses queued before it. The route falls back to
+    /// the response set by `respond` once they are used up.
+    pub fn enqueue(&self, route: &str, response: MockResponse) {
+        self.state
+            .queued
+            .lock()
+            .unwrap()
+            .entry(route.to_string())
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Number of requests made to a route
+    pub fn hits(&self, route: &str) -> usize {
+        self.state
+            .hits
+            .lock()
+            .unwrap()
+            .get(route)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Most requests that were in flight at the same time
+    pub fn max_in_flight(&self) -> usize {
+        self.state.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// A client whose requests are served by this server
+    pub fn client(&self) -> Client {
+        Client::new().with(Redirect(self.url.clone()))
+    }
+
+    /// A pool of `API_POOL_SIZE` clients for every key of `config`
+    pub async fn api_pool(&self, config: &Config) -> ApiPool {
+        let keys = KeyTracker::new(&config.api_keys);
+        let client = self.client().with(keys.clone());
+        let clients = crate::api::connect(&client, config, &keys).await;
+        ApiPool::new(
+            clients,
+            keys,
+            Box::new(MemoryCache::new()),
+            config.cache_ttls(),
+            config.rate_limit_strategy,
+        )
+    }
+
+    /// The state of the server with every upstream served by this server
+    pub async fn web_data(&self, config: Config) -> Data<WebData> {
+        Data::new(WebData {
+            api: self.api_pool(&config).await,
+            minecraft: Resolver::new(self.client(), &config),
+            auction_house: AuctionHouse::new(None),
+            sales: None,
+            bazaar_history: None,
+            readiness: Readiness::new(Duration::from_secs(config.ready_check_interval)),
+            rate_limits: ArcSwap::from_pointee(RateLimits::new(&config)),
+            config: ArcSwap::from_pointee(config),
+            config_file: None,
+        })
+    }
+}
+
+async fn respond(req: HttpRequest, state: Data<MockState>) -> HttpResponse {
+    let path = req.path().trim_start_matches('/');
+    let route = match req.query_string() {
+        "" => path.to_string(),
+        query => format!("{path}?{query}"),
+    };
+    let queued = state
+        .queued
+        .lock()
+        .unwrap()
+        .get_mut(&route)
+        .and_then(VecDeque::pop_front);
+    let response = queued.or_else(|| {
+        let routes = state.routes.lock().unwrap();
+        routes.get(&route).or_else(|| routes.get(path)).cloned()
+    });
+    let Some(response) = response else {
+        return HttpResponse::NotFound().json(json!({"success": false, "cause": "Not found"}));
+    };
+    *state
+        .hits
+        .lock()
+        .unwrap()
+        .entry(path.to_string())
+        .or_default() += 1;
+
+    let in_flight = state.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    state.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+    actix_web::rt::time::sleep(response.delay).await;
+    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+    let mut res = HttpResponse::build(StatusCode::from_u16(response.status).unwrap());
+    for (name, value) in response.headers {
+        res.insert_header((name, value));
+    }
+    res.json(response.body)
+}
+
+/// Sends every request to the mock server, keeping the original host as the first path segment
+struct Redirect(Url);
+
+#[async_trait]
+impl Middleware for Redirect {
+    async fn handle(
+        &self,
+        mut req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> surf::Result<Response> {
+        let mut url = self.0.clone();
+        url.set_path(&format!(
+            "{}{}",
+            req.url().host_str().unwrap_or_default(),
+            req.url().path()
+        ));
+        url.set_query(req.url().query());
+        *AsMut::<surf::http::Request>::as_mut(&mut req).url_mut() = url;
+        next.run(req, client).await
+    }
+}
@@ -1,29 +1,44 @@
-use crate::structs::WebError;
-use actix_web::HttpResponse;
+use crate::error::WebError;
+use actix_web::{HttpResponse, ResponseError};
 use rs_pixel::{util::error::Error, HypixelEndpoint};
 use serde::Serialize;
 use std::sync::Arc;
 
-pub const SERVER_ENDPOINTS: [&str; 19] = [
-    "KEY",
-    "BOOSTERS",
-    "LEADERBOARDS",
-    "PUNISHMENT_STATS",
-    "PLAYER",
-    "GUILD",
-    "COUNTS",
-    "STATUS",
-    "RECENT_GAMES",
-    "SKYBLOCK_PROFILES",
-    "SKYBLOCK_PROFILE",
-    "SKYBLOCK_BINGO",
-    "SKYBLOCK_NEWS",
-    "SKYBLOCK_AUCTION",
-    "SKYBLOCK_AUCTIONS",
-    "SKYBLOCK_AUCTIONS_ENDED",
-    "SKYBLOCK_BAZAAR",
-    "SKYBLOCK_FIRESALES",
-    "RESOURCES",
+/// Server endpoint names and the path they are served on
+pub const SERVER_ENDPOINTS: [(&str, &str); 31] = [
+    ("KEY", "/key"),
+    ("BOOSTERS", "/boosters"),
+    ("LEADERBOARDS", "/leaderboards"),
+    ("PUNISHMENT_STATS", "/punishmentstats"),
+    ("PLAYER", "/player"),
+    ("GUILD", "/guild"),
+    ("COUNTS", "/counts"),
+    ("STATUS", "/status"),
+    ("RECENT_GAMES", "/recentGames"),
+    ("SKYBLOCK_PROFILES", "/skyblock/profiles"),
+    ("SKYBLOCK_PROFILE", "/skyblock/profile"),
+    ("SKYBLOCK_BINGO", "/skyblock/bingo"),
+    ("SKYBLOCK_STATS", "/skyblock/stats"),
+    ("SKYBLOCK_NETWORTH", "/skyblock/networth"),
+    ("SKYBLOCK_NEWS", "/skyblock/news"),
+    ("SKYBLOCK_AUCTION", "/skyblock/auction"),
+    // Before `/skyblock/auctions` so that its sub path matches this endpoint
+    ("SKYBLOCK_AUCTIONS_SEARCH", "/skyblock/auctions/search"),
+    ("SKYBLOCK_AUCTIONS", "/skyblock/auctions"),
+    ("SKYBLOCK_AUCTIONS_ENDED", "/skyblock/auctions_ended"),
+    // Before `/skyblock/bazaar` so that its sub path matches this endpoint
+    ("SKYBLOCK_BAZAAR_HISTORY", "/skyblock/bazaar/history"),
+    ("SKYBLOCK_BAZAAR", "/skyblock/bazaar"),
+    ("SKYBLOCK_LOWEST_BIN", "/skyblock/lowestbin"),
+    ("SKYBLOCK_PRICES", "/skyblock/prices"),
+    ("SKYBLOCK_FIRESALES", "/skyblock/firesales"),
+    ("RESOURCES", "/resources"),
+    ("METRICS", "/metrics"),
+    ("RELOAD", "/admin/reload"),
+    ("BATCH", "/batch"),
+    ("MOJANG_UUID", "/mojang/uuid"),
+    ("MOJANG_USERNAME", "/mojang/username"),
+    ("MOJANG_UUIDS", "/mojang/uuids"),
 ];
 
 pub const HYPIXEL_ENDPOINTS: [(&str, HypixelEndpoint, bool); 30] = [
@@ -115,6 +130,17 @@ pub const HYPIXEL_ENDPOINTS: [(&str, HypixelEndpoint, bool); 30] = [
     ),
 ];
 
+/// Name of the server endpoint serving a request path (or one of its sub paths)
+pub fn server_endpoint(path: &str) -> Option<&'static str> {
+    SERVER_ENDPOINTS
+        .iter()
+        .find(|(_, endpoint_path)| {
+            path.strip_prefix(endpoint_path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .map(|(name, _)| *name)
+}
+
 pub fn ok<T>(value: Arc<T>) -> HttpResponse
 where
     T: Serialize,
@@ -123,15 +149,49 @@ where
 }
 
 pub fn error_response(err: Error) -> HttpResponse {
-    HttpResponse::BadRequest().json(WebError {
-        success: false,
-        cause: err.to_string(),
-    })
+    WebError::hypixel(err).error_response()
 }
 
 pub fn bad_request(cause: &str) -> HttpResponse {
-    HttpResponse::BadRequest().json(WebError {
-        success: false,
-        cause: cause.to_string(),
-    })
+    WebError::bad_request(cause).error_response()
+}
+
+/// Parses a dashed or undashed uuid into its undashed lowercase form
+pub fn parse_uuid(uuid: &str) -> Option<String> {
+    let undashed = if uuid.len() == 36 {
+        if [8, 13, 18, 23]
+            .iter()
+            .any(|&idx| uuid.as_bytes()[idx] != b'-')
+        {
+            return None;
+        }
+        uuid.replace('-', "")
+    } else {
+        uuid.to_string()
+    };
+
+    if undashed.len() == 32 && undashed.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(undashed.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+/// Only checks the length, legacy accounts can have usernames outside of `[A-Za-z0-9_]`
+pub fn is_valid_username(username: &str) -> bool {
+    (1..=16).contains(&username.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames_are_only_checked_for_their_length() {
+        assert!(is_valid_username("Notch"));
+        assert!(is_valid_username("legacy-name"));
+        assert!(is_valid_username(&"é".repeat(16)));
+        assert!(!is_valid_username(""));
+        assert!(!is_valid_username(&"a".repeat(17)));
+    }
 }
@@ -0,0 +1,45 @@
+use std::{
+    env, fs,
+    process::{self, Command},
+};
+
+fn check_config(name: &str, toml: &str) -> (Option<i32>, String, String) {
+    let path = env::temp_dir().join(format!("rs-pixel-backend-{}-{name}.toml", process::id()));
+    fs::write(&path, toml).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rs-pixel-backend"))
+        .arg("--check-config")
+        .arg(format!("--config={}", path.display()))
+        .env_clear()
+        .current_dir(env::temp_dir())
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn valid_configs_are_printed() {
+    let (code, stdout, _) = check_config(
+        "valid",
+        "BASE_URL = \"127.0.0.1\"\nPORT = 8000\nAPI_KEYS = \"0123456789abcdef\"",
+    );
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("\"port\": 8000"), "{stdout}");
+    assert!(!stdout.contains("0123456789abcdef"), "{stdout}");
+}
+
+#[test]
+fn invalid_configs_exit_with_an_error() {
+    let (code, stdout, stderr) = check_config("invalid", "PORT = \"http\"");
+    assert_eq!(code, Some(1));
+    assert!(stdout.is_empty());
+    assert!(stderr.contains("BASE_URL: missing"), "{stderr}");
+    assert!(
+        stderr.contains("PORT: expected a port number, found `http`"),
+        "{stderr}"
+    );
+}